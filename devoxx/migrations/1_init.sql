@@ -0,0 +1,15 @@
+CREATE TABLE Page (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    method TEXT NOT NULL,
+    uri TEXT NOT NULL
+);
+
+CREATE TABLE Page_content (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    response_status INTEGER NOT NULL,
+    headers TEXT NOT NULL,
+    body BLOB NOT NULL,
+    cached_at TEXT NOT NULL,
+    page_id INTEGER,  -- Foreign key column
+    FOREIGN KEY (page_id) REFERENCES Page(id)  -- Define the foreign key constraint
+);
@@ -0,0 +1 @@
+ALTER TABLE Page ADD COLUMN namespace TEXT NOT NULL DEFAULT '';
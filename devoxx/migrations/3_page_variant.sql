@@ -0,0 +1 @@
+ALTER TABLE Page ADD COLUMN variant TEXT NOT NULL DEFAULT '';
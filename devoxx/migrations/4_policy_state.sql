@@ -0,0 +1 @@
+ALTER TABLE Page_content ADD COLUMN policy TEXT;
@@ -1,45 +1,251 @@
-use std::{collections::HashMap, sync::{Arc, Mutex}, time::SystemTime};
+use std::{cell::Cell, collections::hash_map::DefaultHasher, hash::{Hash, Hasher}, sync::{atomic::{AtomicU64, AtomicUsize, Ordering}, Arc, Mutex}};
 
-use super::cache_util::{CacheKey, CachedResponse};
-use axum::{body::{self, Body, HttpBody,  Bytes}, extract::Host, http::{method, Method, uri::{self, PathAndQuery}, HeaderMap, Request, Response, StatusCode, Uri}, response::IntoResponse, routing::*, RequestExt, Router};
+use super::{cache_util::{CacheKey, CachedResponse}, eviction::{Evictor, MemoryConfig}, vary};
+use axum::http::HeaderMap;
+use serde::Serialize;
+
+/// reads recorded per stripe of a shard before they are handed to its evictor
+const READ_BUFFER: usize = 64;
+/// counters and read buffers are split so threads reading one hot key do not contend on a cache line
+const STRIPES: usize = 16;
+
+/// the stored responses of a key, one per `Vary` variant
+type Variants = Vec<Arc<CachedResponse>>;
+
+/// the memory tier. clones share the same shards, so an entry inserted while serving one request
+/// is there for the next. keys are spread over `[cache.memory] shards` shards that each hold an
+/// equal part of `max_bytes`. lookups go to a lock-free map and never wait for writers or block
+/// them, writers of a shard take its evictor lock so the map and the evictor agree on the keys.
+/// the reads recorded for eviction are dropped rather than waited for when a stripe is busy
+#[derive(Debug, Clone)]
+pub struct Buffer {
+    shards : Arc<[Shard]>,
+    counters : Arc<Counters>
+}
 
 #[derive(Debug)]
-pub struct Buffer { 
-    Cache : Mutex<HashMap<CacheKey, Arc<CachedResponse>>>
+struct Shard {
+    /// every key holds its variants, see `cache::vary`
+    entries : papaya::HashMap<CacheKey, Variants>,
+    evictor : Mutex<Evictor<CacheKey>>,
+    /// hits not yet reported to the evictor
+    reads : [Padded<Mutex<Vec<CacheKey>>>; STRIPES]
 }
 
-impl Clone for Buffer {
-    fn clone(&self) -> Self {
-        let cloned_cache = Mutex::new(self.Cache.lock().unwrap().clone());
-        Self { Cache: cloned_cache }
-    }
+#[derive(Debug, Default)]
+struct Counters {
+    hits : Striped,
+    misses : Striped,
+    inserts : AtomicU64,
+    evictions : AtomicU64,
+    rejected : AtomicU64
+}
+
+/// keeps its value on a cache line of its own
+#[derive(Debug, Default)]
+#[repr(align(128))]
+struct Padded<T>(T);
+
+/// a counter summed over stripes, each thread adds to its own
+#[derive(Debug, Default)]
+struct Striped([Padded<AtomicU64>; STRIPES]);
+
+/// counters of the memory tier since the process started
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    pub entries : usize,
+    pub bytes : usize,
+    pub max_bytes : usize,
+    pub shards : usize,
+    pub hits : u64,
+    pub misses : u64,
+    pub inserts : u64,
+    /// keys dropped to make room for others
+    pub evictions : u64,
+    /// responses that were not admitted, larger than a shard or less popular than what they would displace
+    pub rejected : u64
 }
 
 
-impl Buffer { 
-    pub fn new() -> Self { 
-        let mut hashMap = HashMap::new();
-        let mut cache = Mutex::new(hashMap);
-        Buffer{Cache:cache}
+impl Buffer {
+    pub fn new(config: &MemoryConfig) -> Self {
+        let shards = config.shards.max(1);
+        let capacity = config.max_bytes as usize / shards;
+        let shards = (0..shards)
+            .map(|_| Shard { entries: papaya::HashMap::new(), evictor: Mutex::new(Evictor::new(config.eviction, capacity)), reads: Default::default() })
+            .collect();
+        Buffer { shards, counters: Arc::new(Counters::default()) }
+    }
+
+    fn shard(&self, key: &CacheKey) -> &Shard {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % self.shards.len()]
+    }
+
+    /// stores the response, replacing the variant it was produced for. every variant of a key
+    /// counts against the budget and the key is evicted as a whole
+    pub fn insert(&self, key: CacheKey, cached: CachedResponse) {
+        let shard = self.shard(&key);
+        let entries = shard.entries.pin();
+        let mut evictor = shard.evictor.lock().unwrap();
+        let mut variants: Variants = entries
+            .get(&key)
+            .map(|stored| stored.iter().filter(|stored| stored.variant != cached.variant).cloned().collect())
+            .unwrap_or_default();
+        variants.push(Arc::new(cached));
+        let size = variants.iter().map(|cached| weight(cached)).sum();
+        entries.insert(key.clone(), variants);
+        self.counters.inserts.fetch_add(1, Ordering::Relaxed);
+        shard.drain_reads(&mut evictor);
+        for evicted in evictor.insert(key.clone(), size) {
+            entries.remove(&evicted);
+            if evicted == key {
+                self.counters.rejected.fetch_add(1, Ordering::Relaxed);
+            } else {
+                self.counters.evictions.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// the newest variant stored for a request like this one
+    pub fn get(&self, key: &CacheKey, req_headers: &HeaderMap, request_part: &str) -> Option<Arc<CachedResponse>>{
+        let shard = self.shard(key);
+        let found = shard.entries.pin().get(key).and_then(|variants| {
+            variants.iter().filter(|cached| vary::matches(cached, req_headers, request_part)).max_by_key(|cached| cached.cached_at).cloned()
+        });
+        if found.is_some() {
+            self.counters.hits.add(1);
+            shard.record_read(key);
+        } else {
+            self.counters.misses.add(1);
+        }
+        found
+    }
+
+    #[cfg(test)]
+    pub fn contains(&self, key: &CacheKey, req_headers: &HeaderMap, request_part: &str) -> bool{
+        let entries = self.shard(key).entries.pin();
+        entries.get(key).is_some_and(|variants| variants.iter().any(|cached| vary::matches(cached, req_headers, request_part)))
+    }
+
+    /// drops every variant of the key
+    pub fn remove(&self, key: &CacheKey) -> bool {
+        let shard = self.shard(key);
+        let mut evictor = shard.evictor.lock().unwrap();
+        evictor.remove(key);
+        shard.entries.pin().remove(key).is_some()
+    }
 
+    /// drops the variants `expired` picks. they are judged without any lock, then removed at most
+    /// `batch` keys per lock of a shard. returns the number of variants removed
+    pub fn reap<F>(&self, batch: usize, expired: F) -> usize
+    where
+        F: Fn(&CachedResponse) -> bool,
+    {
+        let mut removed = 0;
+        for shard in self.shards.iter() {
+            let entries = shard.entries.pin();
+            let judged: Vec<(CacheKey, Variants, Variants)> = entries
+                .iter()
+                .filter_map(|(key, variants)| {
+                    let kept: Variants = variants.iter().filter(|cached| !expired(cached)).cloned().collect();
+                    (kept.len() < variants.len()).then(|| (key.clone(), variants.clone(), kept))
+                })
+                .collect();
+            for chunk in judged.chunks(batch.max(1)) {
+                let mut evictor = shard.evictor.lock().unwrap();
+                for (key, variants, kept) in chunk {
+                    // a writer may have replaced the variants since they were judged
+                    let unchanged = entries.get(key).is_some_and(|current| {
+                        current.len() == variants.len() && current.iter().zip(variants).all(|(current, judged)| Arc::ptr_eq(current, judged))
+                    });
+                    if !unchanged {
+                        continue;
+                    }
+                    removed += variants.len() - kept.len();
+                    if kept.is_empty() {
+                        entries.remove(key);
+                        evictor.remove(key);
+                    } else {
+                        evictor.resize(key, kept.iter().map(|cached| weight(cached)).sum());
+                        entries.insert(key.clone(), kept.clone());
+                    }
+                }
+            }
+        }
+        removed
     }
 
-    pub async fn insert_into_cache(&mut self, method: Method, uri: Uri, status: StatusCode, headers : HeaderMap, body : Bytes) {
-        let fresh_cache_key = CacheKey::new(method.clone(), uri.clone());
-        let cache_obj = CachedResponse::new(status, headers.clone(), body ,SystemTime::now());
-        self.Cache.lock().unwrap().insert(fresh_cache_key, Arc::new(cache_obj));
+    pub fn stats(&self) -> MemoryStats {
+        let mut stats = MemoryStats { shards: self.shards.len(), ..MemoryStats::default() };
+        for shard in self.shards.iter() {
+            let evictor = shard.evictor.lock().unwrap();
+            stats.entries += evictor.len();
+            stats.bytes += evictor.used();
+            stats.max_bytes += evictor.capacity();
+        }
+        stats.hits = self.counters.hits.sum();
+        stats.misses = self.counters.misses.sum();
+        stats.inserts = self.counters.inserts.load(Ordering::Relaxed);
+        stats.evictions = self.counters.evictions.load(Ordering::Relaxed);
+        stats.rejected = self.counters.rejected.load(Ordering::Relaxed);
+        stats
     }
-    
-    pub async fn get_from_cache(&self, method: Method, uri: Uri) -> Arc<CachedResponse>{
-        let fresh_cache_key = CacheKey::new(method.clone(), uri.clone());
-        let cache = self.Cache.lock().unwrap();
-        let response = cache.get(&fresh_cache_key).unwrap();
-        response.clone()
+}
+
+impl Shard {
+    /// the hit only reaches the evictor when neither lock is contended, a lost read only makes
+    /// eviction a little less precise
+    fn record_read(&self, key: &CacheKey) {
+        let Ok(mut reads) = self.reads[stripe()].0.try_lock() else {
+            return;
+        };
+        if reads.len() < READ_BUFFER {
+            reads.push(key.clone());
+        }
+        if reads.len() >= READ_BUFFER {
+            if let Ok(mut evictor) = self.evictor.try_lock() {
+                reads.drain(..).for_each(|key| evictor.access(&key));
+            }
+        }
     }
-    
-    pub fn is_cached(&self, method: Method, uri: Uri) -> bool{
-        let fresh_cache_key = CacheKey::new(method.clone(), uri.clone());
-        let exists = self.Cache.lock().unwrap().contains_key(&fresh_cache_key);
-        exists
+
+    fn drain_reads(&self, evictor: &mut Evictor<CacheKey>) {
+        for reads in self.reads.iter() {
+            let pending: Vec<CacheKey> = reads.0.lock().unwrap().drain(..).collect();
+            pending.iter().for_each(|key| evictor.access(key));
+        }
+    }
+}
+
+impl Striped {
+    fn add(&self, value: u64) {
+        self.0[stripe()].0.fetch_add(value, Ordering::Relaxed);
     }
-}
\ No newline at end of file
+
+    fn sum(&self) -> u64 {
+        self.0.iter().map(|stripe| stripe.0.load(Ordering::Relaxed)).sum()
+    }
+}
+
+/// the stripe of the calling thread, threads are handed stripes in turn
+fn stripe() -> usize {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    thread_local! {
+        static STRIPE: Cell<Option<usize>> = const { Cell::new(None) };
+    }
+    STRIPE.with(|stripe| {
+        stripe.get().unwrap_or_else(|| {
+            let assigned = NEXT.fetch_add(1, Ordering::Relaxed) % STRIPES;
+            stripe.set(Some(assigned));
+            assigned
+        })
+    })
+}
+
+/// bytes of a stored response counted against the budget, its body and headers
+pub fn weight(cached: &CachedResponse) -> usize {
+    let headers: usize = cached.headers.iter().map(|(name, value)| name.as_str().len() + value.len()).sum();
+    cached.body.len() + headers
+}
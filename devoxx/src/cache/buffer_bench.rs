@@ -0,0 +1,71 @@
+/// throughput of the memory tier on a hot-key workload, run with
+/// `cargo test --release -p devoxx buffer_bench -- --ignored --nocapture`
+#[cfg(test)]
+mod buffer_bench {
+    use std::time::{Duration, Instant, SystemTime};
+
+    use axum::{body::Bytes, http::{HeaderMap, Method, StatusCode, Uri}};
+
+    use crate::cache::{buffer::Buffer, cache_util::{CacheKey, CachedResponse}, eviction::{EvictionPolicy, MemoryConfig}};
+
+    const TASKS_PER_WORKER: usize = 4;
+    const RUN: Duration = Duration::from_millis(500);
+    /// keys every task reads, most requests go to the first few
+    const KEYS: usize = 64;
+
+    fn key(i: usize) -> CacheKey {
+        CacheKey::new(Method::GET, format!("http://origin/hot/{}", i).parse::<Uri>().unwrap())
+    }
+
+    /// requests per second over `workers` tokio worker threads, one write every 100 reads
+    fn throughput(workers: usize, shards: usize) -> f64 {
+        let runtime = tokio::runtime::Builder::new_multi_thread().worker_threads(workers).enable_all().build().unwrap();
+        let buffer = Buffer::new(&MemoryConfig { max_bytes: 64 * 1024 * 1024, eviction: EvictionPolicy::TinyLfu, shards });
+        let body = Bytes::from(vec![b'x'; 2048]);
+        for i in 0..KEYS {
+            buffer.insert(key(i), CachedResponse::new(StatusCode::OK, HeaderMap::new(), body.clone(), SystemTime::now()));
+        }
+        let keys: Vec<CacheKey> = (0..KEYS).map(key).collect();
+        let started = Instant::now();
+        let requests: usize = runtime.block_on(async {
+            let tasks: Vec<_> = (0..workers * TASKS_PER_WORKER)
+                .map(|task| {
+                    let (buffer, keys, body) = (buffer.clone(), keys.clone(), body.clone());
+                    tokio::spawn(async move {
+                        let headers = HeaderMap::new();
+                        let mut requests = 0;
+                        let mut i = task;
+                        while started.elapsed() < RUN {
+                            for _ in 0..100 {
+                                // the lowest bits pick one of 4 hot keys 3 times in 4
+                                i = i.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                                let hot = if (i >> 33) % 4 == 0 { (i >> 40) % KEYS } else { (i >> 40) % 4 };
+                                assert!(buffer.get(&keys[hot], &headers, "").is_some());
+                            }
+                            let written = keys[(i >> 40) % KEYS].clone();
+                            buffer.insert(written, CachedResponse::new(StatusCode::OK, HeaderMap::new(), body.clone(), SystemTime::now()));
+                            requests += 101;
+                            tokio::task::yield_now().await;
+                        }
+                        requests
+                    })
+                })
+                .collect();
+            let mut requests = 0;
+            for task in tasks {
+                requests += task.await.unwrap();
+            }
+            requests
+        });
+        requests as f64 / started.elapsed().as_secs_f64()
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_hot_key_scaling() {
+        println!("{:>8} {:>16} {:>16}", "workers", "1 shard req/s", "16 shards req/s");
+        for workers in [1, 2, 4, 8] {
+            println!("{:>8} {:>16.0} {:>16.0}", workers, throughput(workers, 1), throughput(workers, 16));
+        }
+    }
+}
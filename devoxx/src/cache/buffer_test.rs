@@ -0,0 +1,82 @@
+#[cfg(test)]
+mod buffer_test {
+    use std::time::SystemTime;
+
+    use axum::{body::Bytes, http::{HeaderMap, Method, StatusCode, Uri}};
+
+    use crate::cache::{buffer::{weight, Buffer}, cache_util::{CacheKey, CachedResponse}, eviction::{EvictionPolicy, MemoryConfig}};
+
+    fn entry(body: &'static str) -> CachedResponse {
+        CachedResponse::new(StatusCode::OK, HeaderMap::new(), Bytes::from_static(body.as_bytes()), SystemTime::now())
+    }
+
+    #[tokio::test]
+    async fn test_clones_share_one_tier() {
+        let buffer = Buffer::new(&MemoryConfig::default());
+        let key = CacheKey::new(Method::GET, Uri::from_static("http://origin/page"));
+        // every request works on its own clone of the app state
+        let request = buffer.clone();
+        let inserted = tokio::spawn({
+            let key = key.clone();
+            async move { request.insert(key, entry("first")) }
+        });
+        inserted.await.unwrap();
+        assert_eq!(buffer.get(&key, &HeaderMap::new(), "").unwrap().body, Bytes::from_static(b"first"));
+
+        buffer.insert(key.clone(), entry("second"));
+        let other = buffer.clone();
+        assert_eq!(other.get(&key, &HeaderMap::new(), "").unwrap().body, Bytes::from_static(b"second"));
+        assert!(other.remove(&key));
+        assert!(!buffer.contains(&key, &HeaderMap::new(), ""));
+        assert!(!buffer.remove(&key));
+    }
+
+    #[test]
+    fn test_budget_counts_body_and_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", "text/plain".parse().unwrap());
+        let cached = CachedResponse::new(StatusCode::OK, headers, Bytes::from_static(b"0123456789"), SystemTime::now());
+        assert_eq!(weight(&cached), 10 + "content-type".len() + "text/plain".len());
+
+        let buffer = Buffer::new(&MemoryConfig { max_bytes: 20, eviction: EvictionPolicy::Lru, shards: 1 });
+        let key = |path: &str| CacheKey::new(Method::GET, path.parse::<Uri>().unwrap());
+        buffer.insert(key("/a"), entry("0123456789"));
+        buffer.insert(key("/b"), entry("0123456789"));
+        assert!(buffer.get(&key("/a"), &HeaderMap::new(), "").is_some());
+        buffer.insert(key("/c"), entry("0123456789"));
+        assert!(!buffer.contains(&key("/b"), &HeaderMap::new(), ""));
+        buffer.insert(key("/d"), entry("this body is over the budget"));
+        assert!(buffer.get(&key("/d"), &HeaderMap::new(), "").is_none());
+
+        let stats = buffer.stats();
+        assert_eq!((stats.entries, stats.bytes, stats.max_bytes), (2, 20, 20));
+        assert_eq!((stats.hits, stats.misses, stats.inserts, stats.evictions, stats.rejected), (1, 1, 4, 1, 1));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_shards_stay_within_budget_under_concurrency() {
+        let config = MemoryConfig { max_bytes: 16 * 1024, eviction: EvictionPolicy::TinyLfu, shards: 8 };
+        let buffer = Buffer::new(&config);
+        let tasks: Vec<_> = (0..8)
+            .map(|task| {
+                let buffer = buffer.clone();
+                tokio::spawn(async move {
+                    for i in 0..500 {
+                        let key = CacheKey::new(Method::GET, format!("/{}/{}", task, i % 50).parse::<Uri>().unwrap());
+                        if buffer.get(&key, &HeaderMap::new(), "").is_none() {
+                            buffer.insert(key, entry("a response body of some length"));
+                        }
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        let stats = buffer.stats();
+        assert_eq!((stats.shards, stats.max_bytes), (8, 16 * 1024));
+        assert!(stats.bytes <= stats.max_bytes, "{:?}", stats);
+        assert_eq!(stats.hits + stats.misses, 4000);
+        assert_eq!(stats.inserts, stats.misses);
+    }
+}
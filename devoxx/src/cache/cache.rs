@@ -1,4 +1,4 @@
-use std::{fmt, str::FromStr, sync::{Arc, Mutex}};
+use std::{fmt, str::FromStr, sync::{Arc, Mutex}, time::Duration};
 
 use axum::http::request;
 use reqwest::Client;
@@ -37,8 +37,9 @@ impl FromRedisValue for cacheableBody {
     fn from_redis_value(v: &redis::Value) -> redis::RedisResult<Self> {
         match v { 
             redis::Value::Data(data) => {
-                let value : cacheableBody = serde_json::from_slice(data).unwrap();
-                Ok(value)
+                // entries written before bodies were base64 encoded do not parse, they read as a miss
+                serde_json::from_slice(data)
+                    .map_err(|err| redis::RedisError::from((redis::ErrorKind::TypeError, "invalid cached value", err.to_string())))
             },
             _ => Err(redis::RedisError::from((redis::ErrorKind::TypeError, "invalid value type")))
             
@@ -121,40 +122,62 @@ impl RemoteCacheStore {
        RemoteCacheStore { pool, i: 0 }
     } 
 
-    pub fn get_conn(&mut self) -> Option<&RedisConnection> { 
+    /// false when no connection to redis could be opened at startup
+    pub fn is_connected(&self) -> bool { 
+        !self.pool.connections.is_empty()
+    }
+
+    /// the pool's connections in turn, an error when none could be opened
+    pub fn get_conn(&mut self) -> Result<&RedisConnection, String> { 
+        if self.pool.connections.is_empty() { 
+            return Err("no redis connection".to_string());
+        }
         let index = (self.i as usize) % self.pool.connections.len();
-        let connection = self.pool.connections.get(index);
-        self.i = self.i + 1;
-        connection
+        self.i = self.i.wrapping_add(1);
+        Ok(&self.pool.connections[index])
     }
 
+    /// first stored variant of the key, use `get_variants` to pick one by the request headers
     pub fn get(&mut self, key : Key) -> Result<CachedResponse, String >{
-        let mut conn = self.get_conn();
-        if conn.is_none() { 
-            conn = self.get_conn();
-        }
-        let result = conn.unwrap().lock().unwrap().get::<store::Key, cacheableBody>(key).map_err(|err| err.to_string());
+        let variants = self.get_variants(key)?;
+        variants.into_iter().next().ok_or("key not found".to_string())
+    }
+
+    /// every variant of the key, they live in one redis hash keyed by their `Vary` values
+    pub fn get_variants(&mut self, key : Key) -> Result<Vec<CachedResponse>, String >{
+        let conn = self.get_conn()?;
+        let mut conn = conn.lock().unwrap();
+        let result = conn.hgetall::<store::Key, Vec<(String, cacheableBody)>>(key.clone()).map_err(|err| err.to_string());
         match result { 
-            Ok(cacheable) => { 
-                let cachedResponse = value_to_cache_response(cacheable.value);
-                Ok(cachedResponse)
+            Ok(variants) => { 
+                let cachedResponses = variants.into_iter().map(|(_, cacheable)| value_to_cache_response(cacheable.value)).collect();
+                Ok(cachedResponses)
+            },
+            Err(err) => { 
+                if err.contains("invalid cached value") { 
+                    // written by an older version, dropped so the next store starts the key over
+                    let _ : Result<bool, _> = conn.del(key);
+                }
+                Err(err)
             },
-            Err(err) => Err(err),
         }
     }
 
     pub fn set(&mut self, cacheable: cacheableBody) -> Result<(), String>{
         let key = cacheable.key.clone();
-        let mut conn = self.get_conn().unwrap().lock().unwrap();
-        match conn.set(key, cacheable).map_err(|err| err.to_string()) {
-            Ok(redis::Value::Okay) =>{
+        let variant = cacheable.value.variant.clone();
+        let mut conn = self.get_conn()?.lock().unwrap();
+        let mut result : Result<i64, String> = conn.hset(key.clone(), variant.clone(), &cacheable).map_err(|err| err.to_string());
+        if matches!(&result, Err(err) if err.contains("WRONGTYPE")) { 
+            // entries written before variants were stored as plain strings
+            let _ : Result<bool, _> = conn.del(key.clone());
+            result = conn.hset(key, variant, &cacheable).map_err(|err| err.to_string());
+        }
+        match result {
+            Ok(_) =>{
                 println!("value was successfully set");
                 Ok(())
             },
-            Ok(_) => {
-                println!("unexpected response");
-                Err("unexpected response".to_string())
-            }
             Err(err) => Err(err),
         }
         
@@ -162,9 +185,64 @@ impl RemoteCacheStore {
 
 
     pub fn remove(&mut self, key : Key) -> Result<bool, String> { 
-        let mut conn = self.get_conn().unwrap().lock().unwrap();
+        let mut conn = self.get_conn()?.lock().unwrap();
         let result  : Result<bool, String>= conn.del(key).map_err(|err| err.to_string());
         result 
     }
+
+    /// one `SCAN` step over the stored entries, deletes the variants `expired` picks and drops keys
+    /// left without any. returns the cursor of the next step, 0 once the keyspace was covered, and
+    /// the number of variants removed
+    pub fn reap_batch<F>(&mut self, cursor : u64, batch : usize, expired : F) -> Result<(u64, usize), String>
+    where
+        F: Fn(&CachedResponse) -> bool,
+    {
+        let mut conn = self.get_conn()?.lock().unwrap();
+        // entries are keyed by the json of `store::Key`
+        let (next, keys) : (u64, Vec<Vec<u8>>) = redis::cmd("SCAN").arg(cursor).arg("MATCH").arg("{\"method\"*").arg("COUNT").arg(batch)
+            .query(&mut *conn).map_err(|err| err.to_string())?;
+        let mut removed = 0;
+        for key in keys { 
+            // anything that is not a hash of variants is not ours
+            let Ok(variants) = conn.hgetall::<_, Vec<(String, cacheableBody)>>(&key) else { 
+                continue;
+            };
+            let total = variants.len();
+            let fields : Vec<String> = variants.into_iter().filter_map(|(field, cacheable)| expired(&value_to_cache_response(cacheable.value)).then_some(field)).collect();
+            if fields.is_empty() { 
+                continue;
+            }
+            let result : Result<i64, _> = if fields.len() == total { conn.del(&key) } else { conn.hdel(&key, &fields) };
+            result.map_err(|err| err.to_string())?;
+            removed += fields.len();
+        }
+        Ok((next, removed))
+    }
+
+    /// takes the fetch lease of the key for `ttl` unless another instance holds it
+    pub fn acquire_lease(&mut self, key : &Key, token : &str, ttl : Duration) -> Result<bool, String> { 
+        let mut conn = self.get_conn()?.lock().unwrap();
+        let set : Option<String> = redis::cmd("SET").arg(lease_key(key)).arg(token).arg("NX").arg("PX").arg(ttl.as_millis() as u64)
+            .query(&mut *conn).map_err(|err| err.to_string())?;
+        Ok(set.is_some())
+    }
+
+    /// gives the lease back if `token` still holds it, one that expired and was taken over is left alone
+    pub fn release_lease(&mut self, key : &Key, token : &str) -> Result<bool, String> { 
+        let mut conn = self.get_conn()?.lock().unwrap();
+        let released : i64 = redis::Script::new("if redis.call('get', KEYS[1]) == ARGV[1] then return redis.call('del', KEYS[1]) else return 0 end")
+            .key(lease_key(key)).arg(token)
+            .invoke(&mut *conn).map_err(|err| err.to_string())?;
+        Ok(released == 1)
+    }
+
+    pub fn lease_held(&mut self, key : &Key) -> Result<bool, String> { 
+        let mut conn = self.get_conn()?.lock().unwrap();
+        conn.exists(lease_key(key)).map_err(|err| err.to_string())
+    }
 }
 
+/// redis key of the fetch lease, outside of the `{"method"...` keys the entries are stored under
+pub fn lease_key(key : &Key) -> String { 
+    format!("devoxx:lease:{}", serde_json::to_string(key).unwrap_or_default())
+}
@@ -0,0 +1,145 @@
+use axum::http::{header, HeaderMap};
+
+/// delta-seconds past this are clamped as RFC 9111 section 1.2.2 allows
+const MAX_DELTA_SECONDS: u64 = 2147483648;
+
+/// parsed `Cache-Control` directives of a response or a request, see RFC 9111 section 5.2
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheControl {
+    pub no_store: bool,
+    pub no_cache: bool,
+    pub private: bool,
+    pub public: bool,
+    pub max_age: Option<u64>,
+    pub s_maxage: Option<u64>,
+    pub must_revalidate: bool,
+    pub proxy_revalidate: bool,
+    pub immutable: bool,
+    pub stale_while_revalidate: Option<u64>,
+    pub stale_if_error: Option<u64>,
+    /// request only, `u64::MAX` when the client accepts any staleness
+    pub max_stale: Option<u64>,
+    pub min_fresh: Option<u64>,
+    pub only_if_cached: bool,
+}
+
+impl CacheControl {
+    /// merges every `Cache-Control` line of the headers, the first occurrence of a directive wins
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let mut cache_control = CacheControl::default();
+        for value in headers.get_all(header::CACHE_CONTROL) {
+            if let Ok(value) = value.to_str() {
+                cache_control.apply(value);
+            }
+        }
+        cache_control
+    }
+
+    fn apply(&mut self, value: &str) {
+        for (name, argument) in split_directives(value) {
+            match name.as_str() {
+                "no-store" => self.no_store = true,
+                // the qualified forms (`no-cache="set-cookie"`) are treated like the unqualified ones
+                "no-cache" => self.no_cache = true,
+                "private" => self.private = true,
+                "public" => self.public = true,
+                "must-revalidate" => self.must_revalidate = true,
+                "proxy-revalidate" => self.proxy_revalidate = true,
+                "immutable" => self.immutable = true,
+                "only-if-cached" => self.only_if_cached = true,
+                "max-age" => set_once(&mut self.max_age, argument),
+                "s-maxage" => set_once(&mut self.s_maxage, argument),
+                "stale-while-revalidate" => set_once(&mut self.stale_while_revalidate, argument),
+                "stale-if-error" => set_once(&mut self.stale_if_error, argument),
+                "min-fresh" => set_once(&mut self.min_fresh, argument),
+                // a bare max-stale accepts a response however stale it is
+                "max-stale" if argument.is_none() => self.max_stale = self.max_stale.or(Some(u64::MAX)),
+                "max-stale" => set_once(&mut self.max_stale, argument),
+                _ => {}
+            }
+        }
+    }
+
+    /// freshness lifetime a shared cache should use, `s-maxage` overrides `max-age`
+    pub fn shared_max_age(&self) -> Option<u64> {
+        self.s_maxage.or(self.max_age)
+    }
+
+    /// whether a stale copy may ever be served without revalidating it first
+    pub fn allows_stale(&self) -> bool {
+        !(self.must_revalidate || self.proxy_revalidate || self.s_maxage.is_some() || self.no_cache)
+    }
+}
+
+/// a malformed value makes the response stale, which is the safe reading of a broken header
+fn set_once(slot: &mut Option<u64>, argument: Option<String>) {
+    if slot.is_some() {
+        return;
+    }
+    let seconds = argument.and_then(|value| value.parse::<u64>().ok()).unwrap_or(0);
+    *slot = Some(seconds.min(MAX_DELTA_SECONDS));
+}
+
+/// splits `a, b=1, c="x, y"` into lowercase names and unquoted arguments
+fn split_directives(value: &str) -> Vec<(String, Option<String>)> {
+    let mut directives = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for c in value.chars() {
+        if escaped {
+            current.push(c);
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' if in_quotes => {
+                current.push(c);
+                escaped = true;
+            }
+            '"' => {
+                in_quotes = !in_quotes;
+                current.push(c);
+            }
+            ',' if !in_quotes => {
+                directives.extend(parse_directive(&current));
+                current.clear();
+            }
+            _ => current.push(c),
+        }
+    }
+    directives.extend(parse_directive(&current));
+    directives
+}
+
+fn parse_directive(directive: &str) -> Option<(String, Option<String>)> {
+    let directive = directive.trim();
+    if directive.is_empty() {
+        return None;
+    }
+    let (name, argument) = match directive.split_once('=') {
+        Some((name, argument)) => (name.trim(), Some(unquote(argument.trim()))),
+        None => (directive, None),
+    };
+    Some((name.to_ascii_lowercase(), argument))
+}
+
+fn unquote(value: &str) -> String {
+    match value.strip_prefix('"').and_then(|inner| inner.strip_suffix('"')) {
+        Some(inner) => {
+            let mut unquoted = String::with_capacity(inner.len());
+            let mut chars = inner.chars();
+            while let Some(c) = chars.next() {
+                if c == '\\' {
+                    if let Some(next) = chars.next() {
+                        unquoted.push(next);
+                    }
+                } else {
+                    unquoted.push(c);
+                }
+            }
+            unquoted
+        }
+        None => value.to_string(),
+    }
+}
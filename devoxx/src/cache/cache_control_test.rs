@@ -0,0 +1,53 @@
+#[cfg(test)]
+mod cache_control_test {
+    use axum::http::{HeaderMap, HeaderValue};
+
+    use crate::cache::{cache_control::CacheControl, test_util::cache_control};
+
+    #[test]
+    fn test_directive_lists() {
+        let cc = cache_control("public, max-age=60, must-revalidate");
+        assert!(cc.public);
+        assert!(cc.must_revalidate);
+        assert_eq!(cc.max_age, Some(60));
+        assert!(!cc.allows_stale());
+    }
+
+    #[test]
+    fn test_case_and_whitespace() {
+        let cc = cache_control("  Max-Age = 30 ,NO-CACHE,S-MaxAge=90");
+        assert_eq!(cc.max_age, Some(30));
+        assert_eq!(cc.s_maxage, Some(90));
+        assert!(cc.no_cache);
+        assert_eq!(cc.shared_max_age(), Some(90));
+    }
+
+    #[test]
+    fn test_quoted_values() {
+        let cc = cache_control(r#"private="set-cookie, x-user", max-age="120", stale-while-revalidate=30, stale-if-error=600"#);
+        assert!(cc.private);
+        assert_eq!(cc.max_age, Some(120));
+        assert_eq!(cc.stale_while_revalidate, Some(30));
+        assert_eq!(cc.stale_if_error, Some(600));
+    }
+
+    #[test]
+    fn test_first_value_wins_and_bad_values_are_stale() {
+        assert_eq!(cache_control("max-age=10, max-age=1000").max_age, Some(10));
+        assert_eq!(cache_control("max-age=-5").max_age, Some(0));
+        assert_eq!(cache_control("max-age").max_age, Some(0));
+        assert_eq!(cache_control("max-age=99999999999").max_age, Some(2147483648));
+    }
+
+    #[test]
+    fn test_every_header_line_is_read() {
+        let mut headers = HeaderMap::new();
+        headers.append("cache-control", HeaderValue::from_static("no-store"));
+        headers.append("cache-control", HeaderValue::from_static("immutable, proxy-revalidate"));
+        let cc = CacheControl::from_headers(&headers);
+        assert!(cc.no_store);
+        assert!(cc.immutable);
+        assert!(cc.proxy_revalidate);
+        assert!(!cc.private);
+    }
+}
@@ -11,7 +11,8 @@ mod cache_test {
         let mut store = RemoteCacheStore::new("redis://localhost:6379".to_string(), 5);
         let key = Key { 
             method : "GET".to_string(), 
-            url : "demo".to_string()
+            url : "demo".to_string(),
+            namespace : String::new()
         }; 
         let deleted_result = store.remove(key.clone());
         match deleted_result {
@@ -1,13 +1,13 @@
 
-use std::{collections::HashMap, hash::Hash, sync::{Arc, Mutex}, thread, time::SystemTime, time};
+use std::{hash::Hash, time::SystemTime};
 
-use lazy_static::lazy_static;
 use axum::{body::{self, Body, HttpBody,  Bytes}, extract::Host, http::{method, Method, uri::{self, PathAndQuery}, HeaderMap, Request, Response, StatusCode, Uri}, response::IntoResponse, routing::*, RequestExt, Router};
-use crate::cache::policy_util::CachePolicy;
+use crate::cache::{key::KeyConfig, overrides::Override, policy_util::CachePolicy};
 
 
+/// method, upstream uri and the cache namespace of the vhost that owns the entry
 #[derive(PartialEq, Eq, Clone, Debug)]
-pub struct CacheKey(pub Method,pub Uri);
+pub struct CacheKey(pub Method,pub Uri, pub String);
 
 
 
@@ -16,99 +16,64 @@ pub struct CachedResponse {
     pub status: StatusCode,
     pub headers: HeaderMap,
     pub body : Bytes,
-    pub cached_at: SystemTime
+    pub cached_at: SystemTime,
+    /// request header values named by `Vary` when the response was stored, see `cache::vary`
+    pub variant: String,
+    /// serialized state of the policy backend that admitted the response, see `cache::policy`
+    pub policy: Option<String>,
+    /// the `[[cache.overrides]]` rule that decided how long the entry stays fresh, if any
+    pub ttl_override: Option<Override>
 }
 
 impl CachedResponse  {
     pub fn default() -> Self { 
-        CachedResponse{status: StatusCode::OK, headers: HeaderMap::new(), body: Bytes::new(), cached_at: SystemTime::now()}
+        CachedResponse{status: StatusCode::OK, headers: HeaderMap::new(), body: Bytes::new(), cached_at: SystemTime::now(), variant: String::new(), policy: None, ttl_override: None}
     }
     pub fn new(status : StatusCode, headers : HeaderMap, body: Bytes, cached_at : SystemTime) -> Self {
-        CachedResponse{ status , headers: headers.clone(), body : body, cached_at}
+        CachedResponse{ status , headers: headers.clone(), body : body, cached_at, variant: String::new(), policy: None, ttl_override: None}
+    }
+    pub fn with_variant(mut self, variant : String) -> Self { 
+        self.variant = variant;
+        self
+    }
+    pub fn with_policy(mut self, policy : Option<String>) -> Self { 
+        self.policy = policy;
+        self
+    }
+    pub fn with_override(mut self, ttl_override : Option<Override>) -> Self { 
+        self.ttl_override = ttl_override;
+        self
     }
     pub fn get_parts(&self) -> ( StatusCode, HeaderMap, Bytes,SystemTime) {
         (self.status, self.headers.clone(), self.body.clone(), self.cached_at)
     }
 }
 
-pub struct Cache {
-    inner: Mutex<HashMap<CacheKey, Arc<CachedResponse>>>
-}
-
-
 impl Hash for CacheKey {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         self.0.hash(state);
         self.1.hash(state);
+        self.2.hash(state);
     }
 }
 
 
 impl CacheKey {
     pub fn new(method : Method, uri : Uri) -> Self{
-        CacheKey(method, uri)
+        CacheKey(method, uri, String::new())
         
     }
-}
-lazy_static! {
-    static ref CACHE : Arc<Cache> = Arc::new(Cache {
-        inner: Mutex::new(HashMap::new())
-    });
-}
-
 
-pub fn insert_into_cache(method: Method, uri: Uri, status: StatusCode, headers : HeaderMap, body : Bytes) {
-    let fresh_cache_key = CacheKey::new(method.clone(), uri.clone());
-    let cache_obj = CachedResponse::new(status, headers.clone(), body ,SystemTime::now());
-    CACHE.clone().inner.lock().unwrap().insert(fresh_cache_key, Arc::new(cache_obj));
-}
-
-pub fn get_from_cache(method: Method, uri: Uri) -> Arc<CachedResponse>{
-    let fresh_cache_key = CacheKey::new(method.clone(), uri.clone());
-    let cache = CACHE.inner.lock().unwrap();
-    let response = cache.get(&fresh_cache_key).unwrap();
-    response.clone()
-}
+    pub fn namespaced(namespace : &str, method : Method, uri : Uri) -> Self { 
+        CacheKey(method, uri, namespace.to_string())
+    }
 
-pub fn is_cached(method: Method, uri: Uri) -> bool{
-    let fresh_cache_key = CacheKey::new(method.clone(), uri.clone());
-    let exists = CACHE.inner.lock().unwrap().contains_key(&fresh_cache_key);
-    exists
-}
+    /// key of the uri after the `[cache.key]` normalization
+    pub fn normalized(namespace : &str, method : Method, uri : &Uri, key_config : &KeyConfig) -> Self { 
+        CacheKey::namespaced(namespace, method, key_config.normalize(uri))
+    }
 
-pub async fn remove_stale_cache(cache: Arc<Cache>) { 
-    println!("here");
-    loop { 
-        println!("inside the loop");
-        let cache_clone = cache.clone();
-        for (key ,val) in cache_clone.inner.lock().unwrap().iter() { 
-            println!("checking");
-            let keepCachePolicy = CachePolicy::new(val.headers.clone());
-            if keepCachePolicy.is_stale(val.cached_at) { 
-                println!("hereeeee");
-                if let Some(i) = remove_from_cache(&cache, key.0.clone(), key.1.clone()) { 
-                    println!("removed {}", i);
-                } else { 
-                    println!("nothing to remove");
-                }
-                
-            }
-            println!("exiting");
-            
-        }
-        println!("for exited");
-        //tokio::time::sleep(time::Duration::from_secs(30)).await;
-        thread::sleep(time::Duration::from_secs(10));
+    pub fn namespace(&self) -> &str { 
+        &self.2
     }
 }
-
-pub fn remove_from_cache( cache : &Arc<Cache>, method : Method, uri: Uri) -> Option<u32> {
-    println!("here..."); 
-    let cacheKey = CacheKey::new(method.clone(), uri.clone());
-    let mut cache = CACHE.inner.lock().unwrap();
-    if cache.remove(&cacheKey).is_none()  {
-        return None;
-    } 
-    Some(1 as u32)
- }
-
@@ -0,0 +1,223 @@
+use std::{collections::HashMap, sync::{Arc, Mutex}, time::Duration};
+
+use axum::{body::Bytes, http::{header, HeaderMap, StatusCode}};
+use serde::Deserialize;
+use tokio::sync::watch;
+
+use crate::storage::store::Key;
+
+use super::{cache::RemoteCacheStore, cache_control::CacheControl, cache_util::CacheKey, key::KeyConfig, request::RequestDirectives, vary};
+
+/// concurrent misses on one key wait for a single origin fetch, `[cache.coalesce]`
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CoalesceConfig {
+    pub enabled: bool,
+    /// how long a request waits for the fetch of another one before going to the origin itself
+    pub wait_timeout_secs: u64,
+    /// also coalesce across the instances sharing one redis, through a lease on the key
+    pub distributed: bool,
+    /// lifetime of the lease, a crashed holder keeps the others waiting at most this long
+    pub lease_ttl_secs: u64,
+    /// how often an instance without the lease looks for the entry in the tiers
+    pub poll_interval_ms: u64,
+}
+
+impl Default for CoalesceConfig {
+    fn default() -> Self {
+        CoalesceConfig { enabled: true, wait_timeout_secs: 10, distributed: false, lease_ttl_secs: 30, poll_interval_ms: 100 }
+    }
+}
+
+impl CoalesceConfig {
+    pub fn wait_timeout(&self) -> Duration {
+        Duration::from_secs(self.wait_timeout_secs)
+    }
+
+    pub fn lease_ttl(&self) -> Duration {
+        Duration::from_secs(self.lease_ttl_secs)
+    }
+
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_millis(self.poll_interval_ms)
+    }
+
+    pub fn validate(&self, errors: &mut Vec<String>) {
+        if self.enabled && self.wait_timeout_secs == 0 {
+            errors.push("cache.coalesce.wait_timeout_secs : must be at least 1 when coalescing is enabled".to_string());
+        }
+        if !self.distributed {
+            return;
+        }
+        // the lease is taken by the request leading the fetch in this process
+        if !self.enabled {
+            errors.push("cache.coalesce.distributed : needs enabled = true".to_string());
+        }
+        if !(1..=MAX_LEASE_TTL_SECS).contains(&self.lease_ttl_secs) {
+            errors.push(format!("cache.coalesce.lease_ttl_secs : must be between 1 and {} but found {}", MAX_LEASE_TTL_SECS, self.lease_ttl_secs));
+        }
+        if self.poll_interval_ms == 0 {
+            errors.push("cache.coalesce.poll_interval_ms : must be at least 1".to_string());
+        }
+    }
+}
+
+/// longest lease a config may ask for, so a crashed instance never blocks a key for long
+pub const MAX_LEASE_TTL_SECS: u64 = 300;
+
+/// keys with an origin fetch running in this process
+#[derive(Debug, Clone, Default)]
+pub struct Inflight {
+    fetches: Arc<Mutex<HashMap<CacheKey, watch::Receiver<State>>>>,
+}
+
+/// what the leading fetch got, handed to every request that waited for it
+#[derive(Debug, Clone)]
+pub enum Outcome {
+    Response {
+        status: StatusCode,
+        headers: HeaderMap,
+        body: Bytes,
+        /// the variant the response answers, see `cache::vary`. `None` when it was meant for the
+        /// leading request only or the cache did not keep it
+        variant: Option<String>,
+    },
+    Failed(String),
+}
+
+impl Outcome {
+    /// the outcome as the answer to a request with `req_headers`, `None` when the response answers
+    /// another variant or was meant for the leading request only
+    pub fn for_request(&self, req_headers: &HeaderMap, key_config: &KeyConfig) -> Option<Result<(StatusCode, HeaderMap, Bytes), String>> {
+        match self {
+            Outcome::Failed(err) => Some(Err(err.clone())),
+            Outcome::Response { status, headers, body, variant } => {
+                let wanted = shared_variant(*status, headers, req_headers, key_config)?;
+                (variant.as_ref() == Some(&wanted)).then(|| Ok((*status, headers.clone(), body.clone())))
+            }
+        }
+    }
+}
+
+/// the variant a response answers for a request, `None` when it may not be handed to other
+/// requests: `Vary: *`, `private`, `no-store` and `Set-Cookie` responses, a 304 to the validators
+/// of the request that asked, and responses to `Authorization` requests that do not allow shared
+/// reuse (RFC 9111 section 3.5)
+pub fn shared_variant(status: StatusCode, headers: &HeaderMap, req_headers: &HeaderMap, key_config: &KeyConfig) -> Option<String> {
+    let cache_control = CacheControl::from_headers(headers);
+    if status == StatusCode::NOT_MODIFIED || cache_control.private || cache_control.no_store || headers.contains_key(header::SET_COOKIE) {
+        return None;
+    }
+    if RequestDirectives::from_headers(req_headers).bypasses_storage() {
+        return None;
+    }
+    let reusable = cache_control.public || cache_control.must_revalidate || cache_control.s_maxage.is_some();
+    if req_headers.contains_key(header::AUTHORIZATION) && !reusable {
+        return None;
+    }
+    vary::variant_key(headers, req_headers).map(|variant| vary::with_request_part(variant, &key_config.request_part(req_headers)))
+}
+
+#[derive(Debug, Clone)]
+enum State {
+    Running,
+    Done(Arc<Outcome>),
+}
+
+/// the part a request plays in the fetch of a key
+pub enum Flight {
+    /// fetches and hands its outcome to the waiters with `FlightGuard::finish`
+    Leader(FlightGuard),
+    Waiter(Waiter),
+}
+
+/// how a wait for the leading fetch ended
+pub enum Wait {
+    Done(Arc<Outcome>),
+    /// the leader was dropped without an outcome, cancelled by its client
+    Abandoned,
+    TimedOut,
+}
+
+impl Inflight {
+    pub fn new() -> Self {
+        Inflight::default()
+    }
+
+    /// leads the fetch of `key` when none is running, otherwise waits for the running one
+    pub fn join(&self, key: &CacheKey) -> Flight {
+        let mut fetches = self.fetches.lock().unwrap();
+        if let Some(done) = fetches.get(key) {
+            return Flight::Waiter(Waiter { done: done.clone() });
+        }
+        let (sender, done) = watch::channel(State::Running);
+        fetches.insert(key.clone(), done);
+        Flight::Leader(FlightGuard { fetches: self.fetches.clone(), key: key.clone(), sender })
+    }
+}
+
+pub struct FlightGuard {
+    fetches: Arc<Mutex<HashMap<CacheKey, watch::Receiver<State>>>>,
+    key: CacheKey,
+    sender: watch::Sender<State>,
+}
+
+impl FlightGuard {
+    /// hands `outcome` to the waiters, the key is free for the next fetch once the guard is dropped
+    pub fn finish(&self, outcome: Outcome) {
+        self.sender.send_replace(State::Done(Arc::new(outcome)));
+    }
+}
+
+/// a guard dropped without `finish` closes the channel, its waiters see the fetch as abandoned
+impl Drop for FlightGuard {
+    fn drop(&mut self) {
+        self.fetches.lock().unwrap().remove(&self.key);
+    }
+}
+
+pub struct Waiter {
+    done: watch::Receiver<State>,
+}
+
+impl Waiter {
+    /// the outcome of the leading fetch, unless `timeout` passed first
+    pub async fn wait(mut self, timeout: Duration) -> Wait {
+        match tokio::time::timeout(timeout, self.done.wait_for(|state| matches!(state, State::Done(_)))).await {
+            Ok(Ok(state)) => match &*state {
+                State::Done(outcome) => Wait::Done(outcome.clone()),
+                State::Running => Wait::Abandoned,
+            },
+            Ok(Err(_)) => Wait::Abandoned,
+            Err(_) => Wait::TimedOut,
+        }
+    }
+}
+
+/// the redis lease this instance holds while it fetches a key, given back when dropped. it
+/// expires on its own when the instance dies before that
+pub struct Lease {
+    store: RemoteCacheStore,
+    key: Key,
+    token: String,
+}
+
+impl Lease {
+    /// `None` when another instance holds the lease of the key
+    pub fn acquire(store: &RemoteCacheStore, key: Key, ttl: Duration) -> Result<Option<Lease>, String> {
+        let mut store = store.clone();
+        let token = uuid::Uuid::new_v4().to_string();
+        if !store.acquire_lease(&key, &token, ttl)? {
+            return Ok(None);
+        }
+        Ok(Some(Lease { store, key, token }))
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        if let Err(err) = self.store.release_lease(&self.key, &self.token) {
+            println!("could not release the fetch lease : {}", err);
+        }
+    }
+}
@@ -0,0 +1,152 @@
+#[cfg(test)]
+mod coalesce_test {
+    use std::{sync::{atomic::{AtomicUsize, Ordering}, Arc}, time::Duration};
+
+    use axum::{body::Bytes, http::{HeaderMap, Method, StatusCode, Uri}};
+
+    use crate::cache::{cache::lease_key, cache_util::CacheKey, coalesce::{shared_variant, Flight, Inflight, Outcome, Wait}, key::KeyConfig, test_util::headers};
+    use crate::storage::serializer::cachekey_to_key;
+    use crate::config::config::Config;
+
+    /// what a leader hands over for a request without headers
+    fn response(headers: HeaderMap) -> Outcome {
+        let variant = shared_variant(StatusCode::OK, &headers, &HeaderMap::new(), &KeyConfig::default());
+        Outcome::Response { status: StatusCode::OK, headers, body: Bytes::from_static(b"shared"), variant }
+    }
+
+    fn key() -> CacheKey {
+        CacheKey::new(Method::GET, Uri::from_static("http://origin/slow"))
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_misses_share_one_fetch() {
+        let inflight = Inflight::new();
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let (ready, _) = tokio::sync::broadcast::channel::<()>(1);
+        let tasks: Vec<_> = (0..100)
+            .map(|_| {
+                let (inflight, fetches, mut go) = (inflight.clone(), fetches.clone(), ready.subscribe());
+                tokio::spawn(async move {
+                    go.recv().await.unwrap();
+                    match inflight.join(&key()) {
+                        Flight::Leader(guard) => {
+                            fetches.fetch_add(1, Ordering::SeqCst);
+                            tokio::time::sleep(Duration::from_millis(100)).await;
+                            guard.finish(response(HeaderMap::new()));
+                        }
+                        Flight::Waiter(waiter) => {
+                            let Wait::Done(outcome) = waiter.wait(Duration::from_secs(5)).await else { panic!("expected the leader's outcome") };
+                            let (status, _, body) = outcome.for_request(&HeaderMap::new(), &KeyConfig::default()).unwrap().unwrap();
+                            assert_eq!((status, body), (StatusCode::OK, Bytes::from_static(b"shared")));
+                        }
+                    }
+                })
+            })
+            .collect();
+        ready.send(()).unwrap();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        assert!(matches!(inflight.join(&key()), Flight::Leader(_)));
+    }
+
+    #[tokio::test]
+    async fn test_waiter_times_out_and_a_failed_leader_releases_the_key() {
+        let inflight = Inflight::new();
+        let Flight::Leader(guard) = inflight.join(&key()) else { panic!("expected to lead") };
+        let Flight::Waiter(waiter) = inflight.join(&key()) else { panic!("expected to wait") };
+        assert!(matches!(waiter.wait(Duration::from_millis(20)).await, Wait::TimedOut));
+
+        let Flight::Waiter(waiter) = inflight.join(&key()) else { panic!("expected to wait") };
+        // a cancelled leader drops its guard without an outcome
+        let leader = tokio::spawn(async move {
+            let _guard = guard;
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        });
+        assert!(matches!(waiter.wait(Duration::from_secs(1)).await, Wait::Abandoned));
+        leader.await.unwrap();
+        assert!(matches!(inflight.join(&key()), Flight::Leader(_)));
+    }
+
+    #[test]
+    fn test_outcome_only_reaches_requests_it_answers() {
+        let key_config = KeyConfig::default();
+        let vary = headers(&[("vary", "accept-encoding")]);
+        let gzip = headers(&[("accept-encoding", "gzip")]);
+        let outcome = Outcome::Response { status: StatusCode::OK, headers: vary.clone(), body: Bytes::new(), variant: shared_variant(StatusCode::OK, &vary, &gzip, &key_config) };
+        assert!(outcome.for_request(&gzip, &key_config).is_some());
+        assert!(outcome.for_request(&headers(&[("accept-encoding", "br")]), &key_config).is_none());
+
+        let private = headers(&[("cache-control", "private, max-age=60")]);
+        assert!(shared_variant(StatusCode::OK, &private, &HeaderMap::new(), &key_config).is_none());
+        let cookie = headers(&[("set-cookie", "session=1")]);
+        assert!(shared_variant(StatusCode::OK, &cookie, &HeaderMap::new(), &key_config).is_none());
+        let no_store = headers(&[("cache-control", "no-store")]);
+        assert!(shared_variant(StatusCode::OK, &no_store, &HeaderMap::new(), &key_config).is_none());
+
+        // an origin error reaches every waiter
+        let failed = Outcome::Failed("origin request failed".to_string());
+        assert_eq!(failed.for_request(&gzip, &key_config).unwrap().unwrap_err(), "origin request failed");
+    }
+
+    #[test]
+    fn test_authorized_responses_stay_with_their_request() {
+        let key_config = KeyConfig::default();
+        let authorized = headers(&[("authorization", "Bearer alice")]);
+        // no cache-control at all is not an allowance to share
+        assert!(shared_variant(StatusCode::OK, &HeaderMap::new(), &authorized, &key_config).is_none());
+        assert!(shared_variant(StatusCode::OK, &headers(&[("cache-control", "max-age=60")]), &authorized, &key_config).is_none());
+        for allowed in ["public, max-age=60", "s-maxage=60", "max-age=60, must-revalidate"] {
+            assert!(shared_variant(StatusCode::OK, &headers(&[("cache-control", allowed)]), &authorized, &key_config).is_some(), "{}", allowed);
+        }
+
+        // a waiter carrying credentials does not take the anonymous leader's response either
+        let outcome = response(HeaderMap::new());
+        assert!(outcome.for_request(&HeaderMap::new(), &key_config).is_some());
+        assert!(outcome.for_request(&authorized, &key_config).is_none());
+    }
+
+    #[test]
+    fn test_not_modified_is_not_handed_over() {
+        let key_config = KeyConfig::default();
+        // the leader's own If-None-Match matched, the waiters did not send one
+        let validated = headers(&[("if-none-match", "\"v1\"")]);
+        let not_modified = headers(&[("etag", "\"v1\""), ("cache-control", "public, max-age=60")]);
+        let variant = shared_variant(StatusCode::NOT_MODIFIED, &not_modified, &validated, &key_config);
+        assert!(variant.is_none());
+        let outcome = Outcome::Response { status: StatusCode::NOT_MODIFIED, headers: not_modified, body: Bytes::new(), variant };
+        assert!(outcome.for_request(&HeaderMap::new(), &key_config).is_none());
+    }
+
+    #[test]
+    fn test_coalesce_config() {
+        let config = Config::from_toml("[cache.coalesce]\nwait_timeout_secs = 0\n").unwrap();
+        assert!(config.validate().unwrap_err().contains("cache.coalesce.wait_timeout_secs"));
+        let config = Config::from_toml("[cache.coalesce]\nenabled = false\nwait_timeout_secs = 0\n").unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(Config::default().cache.coalesce.wait_timeout(), Duration::from_secs(10));
+
+        // the lease settings only matter once coalescing is distributed
+        let config = Config::from_toml("[cache.coalesce]\nlease_ttl_secs = 0\npoll_interval_ms = 0\n").unwrap();
+        assert!(config.validate().is_ok());
+        let config = Config::from_toml("[cache.coalesce]\ndistributed = true\nlease_ttl_secs = 301\npoll_interval_ms = 0\n").unwrap();
+        let errors = config.validate().unwrap_err();
+        assert!(errors.contains("cache.coalesce.lease_ttl_secs"));
+        assert!(errors.contains("cache.coalesce.poll_interval_ms"));
+        let config = Config::from_toml("[cache.coalesce]\nenabled = false\ndistributed = true\n").unwrap();
+        assert!(config.validate().unwrap_err().contains("cache.coalesce.distributed"));
+        let config = Config::from_toml("[cache.coalesce]\ndistributed = true\n").unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.cache.coalesce.lease_ttl(), Duration::from_secs(30));
+        assert_eq!(config.cache.coalesce.poll_interval(), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_lease_key_stays_out_of_the_entry_keyspace() {
+        let lease = lease_key(&cachekey_to_key(key()));
+        assert!(lease.starts_with("devoxx:lease:{\"method\""));
+        assert!(lease.contains("/slow"));
+        assert_ne!(lease, lease_key(&cachekey_to_key(CacheKey::new(Method::GET, Uri::from_static("http://origin/fast")))));
+    }
+}
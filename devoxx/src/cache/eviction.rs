@@ -0,0 +1,296 @@
+use std::{collections::{hash_map::DefaultHasher, BTreeMap, HashMap}, hash::{Hash, Hasher}};
+
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EvictionPolicy {
+    /// the entry read longest ago goes first
+    #[default]
+    Lru,
+    /// the entry read least often goes first, ties by recency
+    Lfu,
+    /// W-TinyLFU: a small LRU window in front of a segmented LRU, a newcomer only displaces an
+    /// entry that was requested less often recently
+    TinyLfu,
+}
+
+/// size of the memory tier, `[cache.memory]`. the budget counts bodies and headers
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct MemoryConfig {
+    pub max_bytes: u64,
+    pub eviction: EvictionPolicy,
+    /// independently locked parts of the tier, each gets `max_bytes / shards`
+    pub shards: usize,
+}
+
+impl Default for MemoryConfig {
+    fn default() -> Self {
+        MemoryConfig { max_bytes: 128 * 1024 * 1024, eviction: EvictionPolicy::Lru, shards: 16 }
+    }
+}
+
+impl MemoryConfig {
+    pub fn validate(&self, errors: &mut Vec<String>) {
+        if self.max_bytes == 0 {
+            errors.push("cache.memory.max_bytes : must be at least 1, disable [cache.placement.memory] to turn the tier off".to_string());
+        }
+        if self.shards == 0 || self.shards > 1024 {
+            errors.push(format!("cache.memory.shards : must be between 1 and 1024 but found {}", self.shards));
+        } else if self.max_bytes < self.shards as u64 {
+            errors.push("cache.memory.max_bytes : must be at least one byte per shard".to_string());
+        }
+    }
+}
+
+/// share of the budget given to the admission window of W-TinyLFU
+const WINDOW_PERCENT: usize = 1;
+/// share of the main space given to entries read again after they were admitted
+const PROTECTED_PERCENT: usize = 80;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Segment {
+    /// every entry of the LRU and LFU policies
+    Main = 0,
+    Window = 1,
+    Probation = 2,
+    Protected = 3,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    size: usize,
+    segment: Segment,
+    /// position in its segment, the lowest rank is evicted first
+    rank: (u64, u64),
+    hits: u64,
+}
+
+#[derive(Debug, Clone)]
+struct Order<K> {
+    ranks: BTreeMap<(u64, u64), K>,
+    bytes: usize,
+}
+
+/// decides which keys leave a byte-bounded store. it only tracks keys and sizes, the store keeps
+/// the values and drops whatever `insert` returns
+#[derive(Debug, Clone)]
+pub struct Evictor<K> {
+    policy: EvictionPolicy,
+    capacity: usize,
+    tick: u64,
+    slots: HashMap<K, Slot>,
+    segments: [Order<K>; 4],
+    sketch: Sketch,
+}
+
+impl<K: Hash + Eq + Clone> Evictor<K> {
+    pub fn new(policy: EvictionPolicy, capacity: usize) -> Self {
+        let order = || Order { ranks: BTreeMap::new(), bytes: 0 };
+        Evictor { policy, capacity, tick: 0, slots: HashMap::new(), segments: [order(), order(), order(), order()], sketch: Sketch::new(capacity) }
+    }
+
+    /// bytes held by the tracked keys
+    pub fn used(&self) -> usize {
+        self.segments.iter().map(|order| order.bytes).sum()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    #[cfg(test)]
+    pub fn contains(&self, key: &K) -> bool {
+        self.slots.contains_key(key)
+    }
+
+    /// tracks `key` at `size` bytes, replacing its previous size. returns the keys to drop to stay
+    /// within the budget, `key` itself when it was not admitted
+    pub fn insert(&mut self, key: K, size: usize) -> Vec<K> {
+        let hits = self.unlink(&key).map_or(0, |slot| slot.hits);
+        if size > self.capacity {
+            return vec![key];
+        }
+        match self.policy {
+            EvictionPolicy::Lru | EvictionPolicy::Lfu => {
+                self.link(key, Segment::Main, size, hits + 1);
+                self.shrink(Vec::new())
+            }
+            EvictionPolicy::TinyLfu => {
+                self.sketch.increment(&key);
+                self.link(key, Segment::Window, size, hits + 1);
+                let evicted = self.drain_window();
+                self.shrink(evicted)
+            }
+        }
+    }
+
+    /// records a read of `key`
+    pub fn access(&mut self, key: &K) {
+        if self.policy == EvictionPolicy::TinyLfu {
+            self.sketch.increment(key);
+        }
+        let Some(slot) = self.unlink(key) else {
+            return;
+        };
+        let segment = match slot.segment {
+            Segment::Probation => Segment::Protected,
+            segment => segment,
+        };
+        self.link(key.clone(), segment, slot.size, slot.hits + 1);
+        if segment == Segment::Protected {
+            self.rebalance_protected();
+        }
+    }
+
+    pub fn remove(&mut self, key: &K) -> bool {
+        self.unlink(key).is_some()
+    }
+
+    /// changes the size of a tracked key without counting it as a read, for a store that dropped
+    /// part of its value
+    pub fn resize(&mut self, key: &K, size: usize) {
+        if let Some(slot) = self.slots.get_mut(key) {
+            let order = &mut self.segments[slot.segment as usize];
+            order.bytes = order.bytes - slot.size + size;
+            slot.size = size;
+        }
+    }
+
+    fn link(&mut self, key: K, segment: Segment, size: usize, hits: u64) {
+        self.tick += 1;
+        let rank = match self.policy {
+            EvictionPolicy::Lfu => (hits, self.tick),
+            _ => (0, self.tick),
+        };
+        let order = &mut self.segments[segment as usize];
+        order.ranks.insert(rank, key.clone());
+        order.bytes += size;
+        self.slots.insert(key, Slot { size, segment, rank, hits });
+    }
+
+    fn unlink(&mut self, key: &K) -> Option<Slot> {
+        let slot = self.slots.remove(key)?;
+        let order = &mut self.segments[slot.segment as usize];
+        order.ranks.remove(&slot.rank);
+        order.bytes -= slot.size;
+        Some(slot)
+    }
+
+    fn first(&self, segment: Segment) -> Option<K> {
+        self.segments[segment as usize].ranks.values().next().cloned()
+    }
+
+    /// the entries of the main space in eviction order
+    fn main_order(&self) -> impl Iterator<Item = &K> {
+        [Segment::Main, Segment::Probation, Segment::Protected].into_iter().flat_map(|segment| self.segments[segment as usize].ranks.values())
+    }
+
+    /// moves the entries that overflow the window into probation when there is room or when they
+    /// were requested more often than every entry they would displace
+    fn drain_window(&mut self) -> Vec<K> {
+        let window = (self.capacity * WINDOW_PERCENT / 100).max(1);
+        let mut evicted = Vec::new();
+        while self.segments[Segment::Window as usize].bytes > window {
+            let Some(candidate) = self.first(Segment::Window) else {
+                break;
+            };
+            let slot = self.unlink(&candidate).unwrap();
+            let frequency = self.sketch.frequency(&candidate);
+            let needed = (self.used() + slot.size).saturating_sub(self.capacity);
+            let mut victims = Vec::new();
+            let mut freed = 0;
+            for victim in self.main_order() {
+                if freed >= needed || self.sketch.frequency(victim) >= frequency {
+                    break;
+                }
+                freed += self.slots[victim].size;
+                victims.push(victim.clone());
+            }
+            if freed >= needed {
+                for victim in victims {
+                    self.unlink(&victim);
+                    evicted.push(victim);
+                }
+                self.link(candidate, Segment::Probation, slot.size, slot.hits);
+            } else {
+                evicted.push(candidate);
+            }
+        }
+        evicted
+    }
+
+    fn rebalance_protected(&mut self) {
+        let main = self.capacity - (self.capacity * WINDOW_PERCENT / 100);
+        let protected = main * PROTECTED_PERCENT / 100;
+        while self.segments[Segment::Protected as usize].bytes > protected {
+            let Some(demoted) = self.first(Segment::Protected) else {
+                break;
+            };
+            let slot = self.unlink(&demoted).unwrap();
+            self.link(demoted, Segment::Probation, slot.size, slot.hits);
+        }
+    }
+
+    /// evicts until the budget holds, the window is only emptied once the main space is
+    fn shrink(&mut self, mut evicted: Vec<K>) -> Vec<K> {
+        while self.used() > self.capacity {
+            let Some(victim) = self.main_order().next().cloned().or_else(|| self.first(Segment::Window)) else {
+                break;
+            };
+            self.unlink(&victim);
+            evicted.push(victim);
+        }
+        evicted
+    }
+}
+
+/// count-min sketch of recent request frequencies with 4 bit counters. every counter is halved
+/// once `10 * width` requests were counted so old popularity fades
+#[derive(Debug, Clone)]
+struct Sketch {
+    rows: [Vec<u8>; 4],
+    mask: usize,
+    additions: usize,
+    sample: usize,
+}
+
+const SEEDS: [u64; 4] = [0x9e37_79b9_7f4a_7c15, 0xc2b2_ae3d_27d4_eb4f, 0x1656_67b1_9e37_79f9, 0x27d4_eb2f_1656_67c5];
+
+impl Sketch {
+    /// one counter per 4KiB of budget, between 1024 and 1M per row
+    fn new(capacity: usize) -> Self {
+        let width = (capacity / 4096).clamp(1024, 1 << 20).next_power_of_two();
+        Sketch { rows: [vec![0; width], vec![0; width], vec![0; width], vec![0; width]], mask: width - 1, additions: 0, sample: width * 10 }
+    }
+
+    fn indexes<K: Hash>(&self, key: &K) -> [usize; 4] {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let hash = hasher.finish();
+        SEEDS.map(|seed| ((hash ^ seed).wrapping_mul(seed) >> 32) as usize & self.mask)
+    }
+
+    fn increment<K: Hash>(&mut self, key: &K) {
+        for (row, index) in self.indexes(key).into_iter().enumerate() {
+            let counter = &mut self.rows[row][index];
+            *counter = (*counter + 1).min(15);
+        }
+        self.additions += 1;
+        if self.additions >= self.sample {
+            for row in self.rows.iter_mut() {
+                row.iter_mut().for_each(|counter| *counter /= 2);
+            }
+            self.additions /= 2;
+        }
+    }
+
+    fn frequency<K: Hash>(&self, key: &K) -> u8 {
+        self.indexes(key).into_iter().enumerate().map(|(row, index)| self.rows[row][index]).min().unwrap_or(0)
+    }
+}
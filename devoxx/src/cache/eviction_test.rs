@@ -0,0 +1,72 @@
+#[cfg(test)]
+mod eviction_test {
+    use crate::cache::eviction::{EvictionPolicy, Evictor, MemoryConfig};
+    use crate::config::config::Config;
+
+    #[test]
+    fn test_lru_evicts_least_recently_read() {
+        let mut evictor = Evictor::new(EvictionPolicy::Lru, 300);
+        assert!(evictor.insert(1, 100).is_empty());
+        assert!(evictor.insert(2, 100).is_empty());
+        assert!(evictor.insert(3, 100).is_empty());
+        evictor.access(&1);
+        assert_eq!(evictor.insert(4, 100), vec![2]);
+        assert_eq!(evictor.insert(5, 150), vec![3, 1]);
+        assert_eq!(evictor.used(), 250);
+        assert_eq!(evictor.insert(6, 301), vec![6]);
+        assert!(!evictor.contains(&6));
+    }
+
+    #[test]
+    fn test_lfu_evicts_least_frequently_read() {
+        let mut evictor = Evictor::new(EvictionPolicy::Lfu, 300);
+        evictor.insert(1, 100);
+        evictor.insert(2, 100);
+        evictor.insert(3, 100);
+        evictor.access(&1);
+        evictor.access(&1);
+        evictor.access(&2);
+        assert_eq!(evictor.insert(4, 100), vec![3]);
+        // 4 was read once, 2 twice
+        assert_eq!(evictor.insert(5, 100), vec![4]);
+    }
+
+    #[test]
+    fn test_tinylfu_keeps_popular_entries_from_a_scan() {
+        let mut evictor = Evictor::new(EvictionPolicy::TinyLfu, 10_000);
+        for key in 0..10 {
+            evictor.insert(key, 1000);
+            for _ in 0..5 {
+                evictor.access(&key);
+            }
+        }
+        assert_eq!(evictor.used(), 10_000);
+        // a scan of keys read once must not flush the popular ones
+        for key in 100..200 {
+            evictor.insert(key, 1000);
+            assert!(evictor.used() <= 10_000);
+        }
+        assert!((0..10).filter(|key| evictor.contains(key)).count() >= 9);
+        assert!(evictor.len() <= 10);
+    }
+
+    #[test]
+    fn test_remove_releases_bytes() {
+        let mut evictor = Evictor::new(EvictionPolicy::Lru, 300);
+        evictor.insert("a", 200);
+        evictor.insert("a", 100);
+        assert_eq!(evictor.used(), 100);
+        assert!(evictor.remove(&"a"));
+        assert_eq!(evictor.len(), 0);
+        assert_eq!(evictor.used(), 0);
+    }
+
+    #[test]
+    fn test_memory_config() {
+        let config = Config::from_toml("[cache.memory]\nmax_bytes = 1048576\neviction = \"tinylfu\"\n").unwrap();
+        assert_eq!(config.cache.memory, MemoryConfig { max_bytes: 1048576, eviction: EvictionPolicy::TinyLfu, shards: 16 });
+        assert_eq!(Config::default().cache.memory.eviction, EvictionPolicy::Lru);
+        let err = Config::from_toml("[cache.memory]\nmax_bytes = 0\n").unwrap().validate().unwrap_err();
+        assert!(err.contains("cache.memory.max_bytes"), "{}", err);
+    }
+}
@@ -0,0 +1,103 @@
+use std::time::{Duration, SystemTime};
+
+use axum::http::{header, HeaderMap, StatusCode};
+
+use super::cache_control::CacheControl;
+
+/// status codes a cache may give a heuristic lifetime, RFC 9110 section 15.1
+pub const HEURISTICALLY_CACHEABLE: [u16; 12] = [200, 203, 204, 206, 300, 301, 308, 404, 405, 410, 414, 501];
+/// status codes whose caching rules the proxy understands, RFC 9111 section 3. any other status
+/// is only stored through the negative cache
+pub const UNDERSTOOD: [u16; 15] = [200, 203, 204, 206, 300, 301, 302, 303, 307, 308, 404, 405, 410, 414, 501];
+
+/// lifetime for responses that only carry `Last-Modified`, RFC 9111 section 4.2.2
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Heuristic {
+    /// share of the time since the last modification, 0.1 is the usual choice
+    pub fraction: f64,
+    pub max: Duration,
+}
+
+/// lifetimes the proxy gives responses that carry no explicit freshness, from `[cache]`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Defaults {
+    pub heuristic: Option<Heuristic>,
+    /// lifetime of 404, 410 and 5xx responses, `None` leaves them to the usual rules
+    pub negative_ttl: Option<Duration>,
+}
+
+/// responses the negative cache keeps for a short while so a failing origin is not hammered
+pub fn is_negative(status: StatusCode) -> bool {
+    status == StatusCode::NOT_FOUND || status == StatusCode::GONE || status.is_server_error()
+}
+
+/// the negative ttl applies to `status`
+pub fn negatively_cached(status: StatusCode, defaults: Defaults) -> bool {
+    defaults.negative_ttl.is_some() && is_negative(status)
+}
+
+pub fn header_date(headers: &HeaderMap, name: header::HeaderName) -> Option<SystemTime> {
+    headers.get(name)?.to_str().ok().and_then(|value| httpdate::parse_http_date(value.trim()).ok())
+}
+
+/// RFC 9111 section 4.2.1, `None` when the response has no lifetime or a status the proxy does
+/// not understand. explicit freshness wins, then the negative ttl, then the heuristic
+pub fn freshness_lifetime(
+    status: StatusCode,
+    headers: &HeaderMap,
+    cache_control: &CacheControl,
+    response_time: SystemTime,
+    defaults: Defaults,
+) -> Option<Duration> {
+    let negative = negatively_cached(status, defaults);
+    if !(negative || UNDERSTOOD.contains(&status.as_u16())) {
+        return None;
+    }
+    if let Some(explicit) = explicit_lifetime(headers, cache_control, response_time) {
+        return Some(explicit);
+    }
+    if negative {
+        return defaults.negative_ttl;
+    }
+    let heuristic = defaults.heuristic?;
+    if !(cache_control.public || HEURISTICALLY_CACHEABLE.contains(&status.as_u16())) {
+        return None;
+    }
+    let last_modified = header_date(headers, header::LAST_MODIFIED)?;
+    let date = header_date(headers, header::DATE).unwrap_or(response_time);
+    let since_modified = date.duration_since(last_modified).ok()?;
+    Some(since_modified.mul_f64(heuristic.fraction).min(heuristic.max))
+}
+
+/// lifetime from `s-maxage`, `max-age` or `Expires`
+fn explicit_lifetime(headers: &HeaderMap, cache_control: &CacheControl, response_time: SystemTime) -> Option<Duration> {
+    if let Some(max_age) = cache_control.shared_max_age() {
+        return Some(Duration::from_secs(max_age));
+    }
+    if let Some(expires) = headers.get(header::EXPIRES) {
+        // an Expires value that does not parse, like "0", means already expired
+        let Some(expires) = expires.to_str().ok().and_then(|value| httpdate::parse_http_date(value.trim()).ok()) else {
+            return Some(Duration::ZERO);
+        };
+        let date = header_date(headers, header::DATE).unwrap_or(response_time);
+        return Some(expires.duration_since(date).unwrap_or(Duration::ZERO));
+    }
+    None
+}
+
+/// RFC 9111 section 4.2.3, `response_time` is when the entry was stored and the request delay is
+/// taken as zero since the tiers do not keep the request time
+pub fn current_age(headers: &HeaderMap, response_time: SystemTime, now: SystemTime) -> Duration {
+    let age_value = headers
+        .get(header::AGE)
+        .and_then(|age| age.to_str().ok())
+        .and_then(|age| age.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(Duration::ZERO);
+    let apparent_age = header_date(headers, header::DATE)
+        .and_then(|date| response_time.duration_since(date).ok())
+        .unwrap_or(Duration::ZERO);
+    let corrected_initial_age = apparent_age.max(age_value);
+    let resident_time = now.duration_since(response_time).unwrap_or(Duration::ZERO);
+    corrected_initial_age + resident_time
+}
@@ -0,0 +1,109 @@
+#[cfg(test)]
+mod freshness_test {
+    use std::time::{Duration, SystemTime};
+
+    use axum::http::{HeaderMap, HeaderValue, StatusCode};
+
+    use crate::cache::{
+        cache_control::CacheControl,
+        freshness::{current_age, freshness_lifetime, Defaults, Heuristic},
+        policy_util::CachePolicy,
+    };
+
+    const HEURISTIC: Option<Heuristic> = Some(Heuristic { fraction: 0.1, max: Duration::from_secs(86400) });
+
+    fn date(time: SystemTime) -> HeaderValue {
+        HeaderValue::from_str(&httpdate::fmt_http_date(time)).unwrap()
+    }
+
+    fn lifetime(headers: &HeaderMap, heuristic: Option<Heuristic>) -> Option<Duration> {
+        status_lifetime(StatusCode::OK, headers, Defaults { heuristic, negative_ttl: None })
+    }
+
+    fn status_lifetime(status: StatusCode, headers: &HeaderMap, defaults: Defaults) -> Option<Duration> {
+        freshness_lifetime(status, headers, &CacheControl::from_headers(headers), SystemTime::now(), defaults)
+    }
+
+    #[test]
+    fn test_expires_relative_to_date() {
+        let origin_now = SystemTime::now() - Duration::from_secs(3600);
+        let mut headers = HeaderMap::new();
+        headers.insert("date", date(origin_now));
+        headers.insert("expires", date(origin_now + Duration::from_secs(300)));
+        // the origin clock is an hour behind ours, the lifetime still comes out as 300 seconds
+        assert_eq!(lifetime(&headers, None), Some(Duration::from_secs(300)));
+    }
+
+    #[test]
+    fn test_max_age_beats_expires_and_bad_expires_is_expired() {
+        let mut headers = HeaderMap::new();
+        headers.insert("cache-control", HeaderValue::from_static("max-age=60"));
+        headers.insert("expires", HeaderValue::from_static("0"));
+        assert_eq!(lifetime(&headers, None), Some(Duration::from_secs(60)));
+        headers.remove("cache-control");
+        assert_eq!(lifetime(&headers, None), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_heuristic_from_last_modified() {
+        let now = SystemTime::now();
+        let mut headers = HeaderMap::new();
+        headers.insert("date", date(now));
+        headers.insert("last-modified", date(now - Duration::from_secs(10000)));
+        assert_eq!(lifetime(&headers, None), None);
+        assert_eq!(lifetime(&headers, HEURISTIC), Some(Duration::from_secs(1000)));
+        headers.insert("last-modified", date(now - Duration::from_secs(100 * 86400)));
+        assert_eq!(lifetime(&headers, HEURISTIC), Some(Duration::from_secs(86400)));
+        let not_heuristic = freshness_lifetime(StatusCode::CREATED, &headers, &CacheControl::default(), now, Defaults { heuristic: HEURISTIC, negative_ttl: None });
+        assert_eq!(not_heuristic, None);
+    }
+
+    #[test]
+    fn test_only_understood_statuses_have_a_lifetime() {
+        let mut headers = HeaderMap::new();
+        headers.insert("cache-control", HeaderValue::from_static("max-age=60"));
+        let defaults = Defaults::default();
+        assert_eq!(status_lifetime(StatusCode::FOUND, &headers, defaults), Some(Duration::from_secs(60)));
+        assert_eq!(status_lifetime(StatusCode::NOT_IMPLEMENTED, &headers, defaults), Some(Duration::from_secs(60)));
+        assert_eq!(status_lifetime(StatusCode::CREATED, &headers, defaults), None);
+        assert_eq!(status_lifetime(StatusCode::FORBIDDEN, &headers, defaults), None);
+        assert_eq!(status_lifetime(StatusCode::SERVICE_UNAVAILABLE, &headers, defaults), None);
+    }
+
+    #[test]
+    fn test_negative_ttl() {
+        let defaults = Defaults { heuristic: HEURISTIC, negative_ttl: Some(Duration::from_secs(10)) };
+        let mut headers = HeaderMap::new();
+        for status in [StatusCode::NOT_FOUND, StatusCode::GONE, StatusCode::BAD_GATEWAY, StatusCode::INTERNAL_SERVER_ERROR] {
+            assert_eq!(status_lifetime(status, &headers, defaults), Some(Duration::from_secs(10)), "{}", status);
+        }
+        // other statuses are untouched and explicit freshness still wins
+        assert_eq!(status_lifetime(StatusCode::OK, &headers, defaults), None);
+        assert_eq!(status_lifetime(StatusCode::FORBIDDEN, &headers, defaults), None);
+        headers.insert("cache-control", HeaderValue::from_static("max-age=300"));
+        assert_eq!(status_lifetime(StatusCode::NOT_FOUND, &headers, defaults), Some(Duration::from_secs(300)));
+        assert_eq!(status_lifetime(StatusCode::SERVICE_UNAVAILABLE, &headers, defaults), Some(Duration::from_secs(300)));
+    }
+
+    #[test]
+    fn test_current_age_uses_age_and_date() {
+        let now = SystemTime::now();
+        let stored = now - Duration::from_secs(10);
+        let mut headers = HeaderMap::new();
+        headers.insert("age", HeaderValue::from_static("50"));
+        let age = current_age(&headers, stored, now);
+        assert_eq!(age.as_secs(), 60);
+        headers.insert("date", date(stored - Duration::from_secs(100)));
+        assert_eq!(current_age(&headers, stored, now).as_secs(), 110);
+    }
+
+    #[test]
+    fn test_policy_counts_upstream_age() {
+        let mut headers = HeaderMap::new();
+        headers.insert("cache-control", HeaderValue::from_static("max-age=60"));
+        headers.insert("age", HeaderValue::from_static("59"));
+        let policy = CachePolicy::new(headers);
+        assert!(policy.is_cacheable());
+        assert!(policy.is_stale(SystemTime::now() - Duration::from_secs(2)));
+    }
+}
@@ -0,0 +1,50 @@
+use axum::http::{header, uri::PathAndQuery, HeaderMap, Method, StatusCode, Uri};
+
+/// methods that do not change the origin, RFC 9110 section 9.2.1
+pub fn is_safe(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE)
+}
+
+/// an unsafe request that succeeded invalidates what the cache holds for it, RFC 9111 section 4.4
+pub fn invalidates(method: &Method, status: StatusCode) -> bool {
+    !is_safe(method) && (status.is_success() || status.is_redirection())
+}
+
+/// the target uri and the `Location` / `Content-Location` of the response, resolved against it.
+/// uris on another host are skipped so a response cannot purge entries it does not own
+pub fn invalidation_targets(target: &Uri, response_headers: &HeaderMap) -> Vec<Uri> {
+    let mut targets = vec![target.clone()];
+    for name in [header::LOCATION, header::CONTENT_LOCATION] {
+        let resolved = response_headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| resolve(target, value.trim()));
+        if let Some(uri) = resolved {
+            if !targets.contains(&uri) {
+                targets.push(uri);
+            }
+        }
+    }
+    targets
+}
+
+/// resolves an absolute or relative reference against the target, `None` for other hosts
+fn resolve(target: &Uri, reference: &str) -> Option<Uri> {
+    if reference.contains("://") {
+        let reference: Uri = reference.parse().ok()?;
+        let same_host = reference.authority() == target.authority() && reference.scheme() == target.scheme();
+        return same_host.then_some(reference);
+    }
+    let path_and_query = if reference.starts_with('/') {
+        reference.parse::<PathAndQuery>().ok()?
+    } else {
+        // a relative path replaces the last segment of the target path
+        let base = target.path();
+        let directory = &base[..base.rfind('/').map_or(0, |index| index + 1)];
+        let joined = if directory.is_empty() { format!("/{}", reference) } else { format!("{}{}", directory, reference) };
+        joined.parse::<PathAndQuery>().ok()?
+    };
+    let mut parts = target.clone().into_parts();
+    parts.path_and_query = Some(path_and_query);
+    Uri::from_parts(parts).ok()
+}
@@ -0,0 +1,44 @@
+#[cfg(test)]
+mod invalidate_test {
+    use axum::http::{HeaderMap, HeaderValue, Method, StatusCode, Uri};
+
+    use crate::cache::invalidate::{invalidates, invalidation_targets, is_safe};
+
+    fn targets(location: Option<&'static str>, content_location: Option<&'static str>) -> Vec<String> {
+        let mut headers = HeaderMap::new();
+        if let Some(location) = location {
+            headers.insert("location", HeaderValue::from_static(location));
+        }
+        if let Some(content_location) = content_location {
+            headers.insert("content-location", HeaderValue::from_static(content_location));
+        }
+        let target = Uri::from_static("http://localhost:3000/users/7?full=1");
+        invalidation_targets(&target, &headers).iter().map(|uri| uri.to_string()).collect()
+    }
+
+    #[test]
+    fn test_only_successful_unsafe_requests_invalidate() {
+        assert!(is_safe(&Method::HEAD));
+        assert!(!is_safe(&Method::PATCH));
+        assert!(invalidates(&Method::POST, StatusCode::CREATED));
+        assert!(invalidates(&Method::DELETE, StatusCode::SEE_OTHER));
+        assert!(!invalidates(&Method::PUT, StatusCode::INTERNAL_SERVER_ERROR));
+        assert!(!invalidates(&Method::GET, StatusCode::OK));
+    }
+
+    #[test]
+    fn test_location_headers_are_resolved() {
+        assert_eq!(targets(None, None), vec!["http://localhost:3000/users/7?full=1"]);
+        assert_eq!(
+            targets(Some("/users"), Some("8")),
+            vec!["http://localhost:3000/users/7?full=1", "http://localhost:3000/users", "http://localhost:3000/users/8"]
+        );
+        assert_eq!(targets(Some("http://localhost:3000/users/9"), None)[1], "http://localhost:3000/users/9");
+    }
+
+    #[test]
+    fn test_other_hosts_are_not_invalidated() {
+        assert_eq!(targets(Some("http://elsewhere:3000/users"), Some("https://localhost:3000/users")).len(), 1);
+        assert_eq!(targets(Some("/users/7?full=1"), None).len(), 1);
+    }
+}
@@ -0,0 +1,145 @@
+use axum::http::{header, uri::{Authority, PathAndQuery}, HeaderMap, Uri};
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TrailingSlash {
+    /// `/a` and `/a/` stay different entries
+    #[default]
+    Keep,
+    /// `/a/` is stored as `/a`
+    Strip,
+    /// `/a` is stored as `/a/`, paths whose last segment has an extension such as `/app.js` are left alone
+    Add,
+}
+
+/// how a request uri turns into a cache key, `[cache.key]`. the normalized uri is only used to
+/// look entries up and store them, the origin always gets the uri the client sent
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyConfig {
+    /// `?b=2&a=1` and `?a=1&b=2` share an entry
+    pub sort_query: bool,
+    /// query parameters left out of the key, `utm_*` matches every parameter starting with `utm_`
+    pub drop_params: Vec<String>,
+    /// when not empty only these query parameters are kept, same patterns as `drop_params`
+    pub keep_params: Vec<String>,
+    pub lowercase_host: bool,
+    pub trailing_slash: TrailingSlash,
+    /// request headers whose values are part of the key
+    pub headers: Vec<String>,
+    /// request cookies whose values are part of the key
+    pub cookies: Vec<String>,
+}
+
+impl Default for KeyConfig {
+    fn default() -> Self {
+        KeyConfig {
+            sort_query: false,
+            drop_params: Vec::new(),
+            keep_params: Vec::new(),
+            lowercase_host: true,
+            trailing_slash: TrailingSlash::Keep,
+            headers: Vec::new(),
+            cookies: Vec::new(),
+        }
+    }
+}
+
+impl KeyConfig {
+    /// the uri the key is built from, unchanged when it can not be rebuilt
+    pub fn normalize(&self, uri: &Uri) -> Uri {
+        let mut parts = uri.clone().into_parts();
+        if let (true, Some(authority)) = (self.lowercase_host, &parts.authority) {
+            parts.authority = authority.as_str().to_ascii_lowercase().parse::<Authority>().ok().or(parts.authority);
+        }
+        let path = self.normalize_path(uri.path());
+        let query = self.normalize_query(uri.query().unwrap_or(""));
+        let path_and_query = if query.is_empty() { path } else { format!("{}?{}", path, query) };
+        parts.path_and_query = path_and_query.parse::<PathAndQuery>().ok().or(parts.path_and_query);
+        Uri::from_parts(parts).unwrap_or_else(|_| uri.clone())
+    }
+
+    fn normalize_path(&self, path: &str) -> String {
+        let path = if path.is_empty() { "/" } else { path };
+        match self.trailing_slash {
+            TrailingSlash::Keep => path.to_string(),
+            TrailingSlash::Strip if path.len() > 1 => {
+                let stripped = path.trim_end_matches('/');
+                if stripped.is_empty() { "/".to_string() } else { stripped.to_string() }
+            }
+            TrailingSlash::Add if !path.ends_with('/') && !path.rsplit('/').next().unwrap_or("").contains('.') => format!("{}/", path),
+            _ => path.to_string(),
+        }
+    }
+
+    /// parameters are compared by their raw name, values are never decoded
+    fn normalize_query(&self, query: &str) -> String {
+        let mut params: Vec<&str> = query
+            .split('&')
+            .filter(|param| !param.is_empty())
+            .filter(|param| {
+                let name = param.split('=').next().unwrap_or("");
+                if !self.keep_params.is_empty() {
+                    return self.keep_params.iter().any(|pattern| param_matches(pattern, name));
+                }
+                !self.drop_params.iter().any(|pattern| param_matches(pattern, name))
+            })
+            .collect();
+        if self.sort_query {
+            params.sort();
+        }
+        params.join("&")
+    }
+
+    /// the configured request headers and cookies as a part of the variant, see `cache::vary`.
+    /// empty when the key takes none of them
+    pub fn request_part(&self, req_headers: &HeaderMap) -> String {
+        let mut parts = Vec::new();
+        for name in &self.headers {
+            let values: Vec<&str> = req_headers.get_all(name.as_str()).iter().filter_map(|value| value.to_str().ok()).map(str::trim).collect();
+            parts.push(format!("header:{}={}", name.to_ascii_lowercase(), values.join(",")));
+        }
+        for name in &self.cookies {
+            let value = cookie(req_headers, name).unwrap_or("");
+            parts.push(format!("cookie:{}={}", name, value));
+        }
+        parts.join("\n")
+    }
+
+    pub fn validate(&self, errors: &mut Vec<String>) {
+        if !self.drop_params.is_empty() && !self.keep_params.is_empty() {
+            errors.push("cache.key : drop_params and keep_params can not be combined".to_string());
+        }
+        for name in &self.headers {
+            if header::HeaderName::from_bytes(name.as_bytes()).is_err() {
+                errors.push(format!("cache.key.headers : {:?} is not a header name", name));
+            }
+        }
+        for name in &self.cookies {
+            if name.is_empty() || name.contains(['=', ';', ' ']) {
+                errors.push(format!("cache.key.cookies : {:?} is not a cookie name", name));
+            }
+        }
+    }
+}
+
+/// `pattern` is a parameter name or a prefix ending in `*`
+fn param_matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => name == pattern,
+    }
+}
+
+/// value of the first cookie called `name` across every `Cookie` header
+fn cookie<'a>(req_headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    req_headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(cookie_name, _)| *cookie_name == name)
+        .map(|(_, value)| value)
+}
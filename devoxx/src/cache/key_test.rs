@@ -0,0 +1,69 @@
+#[cfg(test)]
+mod key_test {
+    use axum::http::{HeaderMap, HeaderValue, Method, Uri};
+
+    use crate::cache::{cache_util::CacheKey, key::{KeyConfig, TrailingSlash}, vary::with_request_part};
+    use crate::config::config::Config;
+    use crate::storage::serializer::cachekey_to_key;
+
+    fn normalize(config: &KeyConfig, uri: &'static str) -> String {
+        config.normalize(&Uri::from_static(uri)).to_string()
+    }
+
+    #[test]
+    fn test_default_only_lowercases_the_host() {
+        let config = KeyConfig::default();
+        assert_eq!(normalize(&config, "http://Origin:3000/a/?b=2&a=1"), "http://origin:3000/a/?b=2&a=1");
+    }
+
+    #[test]
+    fn test_sorted_and_filtered_query() {
+        let config = KeyConfig { sort_query: true, drop_params: vec!["utm_*".to_string(), "fbclid".to_string()], ..KeyConfig::default() };
+        assert_eq!(normalize(&config, "http://o/p?b=2&utm_source=x&a=1&fbclid=y"), "http://o/p?a=1&b=2");
+        assert_eq!(normalize(&config, "http://o/p?utm_medium=mail"), "http://o/p");
+        let config = KeyConfig { keep_params: vec!["page".to_string()], ..KeyConfig::default() };
+        assert_eq!(normalize(&config, "http://o/list?sort=asc&page=2"), "http://o/list?page=2");
+    }
+
+    #[test]
+    fn test_trailing_slash() {
+        let strip = KeyConfig { trailing_slash: TrailingSlash::Strip, ..KeyConfig::default() };
+        assert_eq!(normalize(&strip, "http://o/docs/?q=1"), "http://o/docs?q=1");
+        assert_eq!(normalize(&strip, "http://o/"), "http://o/");
+        let add = KeyConfig { trailing_slash: TrailingSlash::Add, ..KeyConfig::default() };
+        assert_eq!(normalize(&add, "http://o/docs"), "http://o/docs/");
+        assert_eq!(normalize(&add, "http://o/app.js"), "http://o/app.js");
+    }
+
+    #[test]
+    fn test_equivalent_uris_share_a_key() {
+        let config = Config::from_toml("[cache.key]\nsort_query = true\ndrop_params = [\"utm_*\"]\n").unwrap();
+        assert!(config.validate().is_ok());
+        let a = CacheKey::normalized("site", Method::GET, &Uri::from_static("http://o/p?a=1&b=2"), &config.cache.key);
+        let b = CacheKey::normalized("site", Method::GET, &Uri::from_static("http://o/p?b=2&utm_campaign=z&a=1"), &config.cache.key);
+        assert_eq!(a, b);
+        assert_eq!(cachekey_to_key(a).url, cachekey_to_key(b).url);
+    }
+
+    #[test]
+    fn test_request_part_from_headers_and_cookies() {
+        let config = KeyConfig { headers: vec!["X-Device".to_string()], cookies: vec!["currency".to_string()], ..KeyConfig::default() };
+        let mut headers = HeaderMap::new();
+        headers.insert("x-device", HeaderValue::from_static("mobile"));
+        headers.insert("cookie", HeaderValue::from_static("session=abc; currency=EUR"));
+        let part = config.request_part(&headers);
+        assert_eq!(part, "header:x-device=mobile\ncookie:currency=EUR");
+        assert_eq!(with_request_part(String::new(), &part), part);
+        assert_eq!(with_request_part("accept=json".to_string(), ""), "accept=json");
+        assert_eq!(KeyConfig::default().request_part(&headers), "");
+    }
+
+    #[test]
+    fn test_key_validation() {
+        let config = Config::from_toml("[cache.key]\ndrop_params = [\"a\"]\nkeep_params = [\"b\"]\nheaders = [\"bad header\"]\ncookies = [\"a=b\"]\n").unwrap();
+        let err = config.validate().unwrap_err();
+        assert!(err.contains("drop_params and keep_params"), "{}", err);
+        assert!(err.contains("cache.key.headers"), "{}", err);
+        assert!(err.contains("cache.key.cookies"), "{}", err);
+    }
+}
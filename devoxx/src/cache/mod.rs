@@ -1,5 +1,42 @@
 pub mod policy_util;
+pub mod policy;
+pub mod cache_control;
+pub mod freshness;
+pub mod revalidate;
+pub mod vary;
+pub mod refresh;
+pub mod invalidate;
+pub mod request;
+pub mod status;
+pub mod placement;
+pub mod overrides;
+pub mod key;
+pub mod range;
+pub mod eviction;
+pub mod reaper;
+pub mod coalesce;
 pub mod cache_util;
 pub mod cache;
 pub mod buffer;
-mod cache_test;
\ No newline at end of file
+#[cfg(test)]
+pub mod test_util;
+mod cache_test;
+mod cache_control_test;
+mod policy_util_test;
+mod policy_test;
+mod freshness_test;
+mod revalidate_test;
+mod vary_test;
+mod refresh_test;
+mod invalidate_test;
+mod request_test;
+mod status_test;
+mod placement_test;
+mod overrides_test;
+mod key_test;
+mod range_test;
+mod buffer_test;
+mod eviction_test;
+mod buffer_bench;
+mod reaper_test;
+mod coalesce_test;
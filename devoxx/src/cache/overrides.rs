@@ -0,0 +1,222 @@
+use std::time::{Duration, SystemTime};
+
+use axum::http::{header, HeaderMap, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::routing::vhost::HostPattern;
+
+use super::{cache_control::CacheControl, freshness::UNDERSTOOD, placement::media_type_matches, policy::Policy, vary::vary_names};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OverrideAction {
+    /// cache for `ttl_secs` whatever the origin said, `no-store` and `private` are still honored
+    Force,
+    /// never keep the response fresh for longer than `ttl_secs`
+    Cap,
+    /// never store the response
+    Never,
+}
+
+/// one `[[cache.overrides]]` rule, matchers that are left out match every request
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct OverrideRule {
+    /// recorded with the entries the rule applied to, defaults to `cache.overrides[<index>]`
+    pub name: Option<String>,
+    /// incoming host, exact, `*.domain` or `*`
+    pub host: Option<String>,
+    /// path prefix of the incoming request such as `/api` or `/api/*`, matched on whole segments
+    pub path: Option<String>,
+    #[serde(default)]
+    pub methods: Vec<String>,
+    /// media types of the response such as `application/json` or `image/*`
+    #[serde(default)]
+    pub content_types: Vec<String>,
+    pub action: OverrideAction,
+    pub ttl_secs: Option<u64>,
+}
+
+/// the rule that changed how an entry is cached, stored with the entry so the reason stays visible
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Override {
+    pub rule: String,
+    pub action: OverrideAction,
+    pub ttl_secs: Option<u64>,
+}
+
+/// the configured rules ready for matching, the first rule that matches wins
+#[derive(Debug, Clone, Default)]
+pub struct Overrides {
+    rules: Vec<CompiledRule>,
+}
+
+#[derive(Debug, Clone)]
+struct CompiledRule {
+    name: String,
+    host: Option<HostPattern>,
+    /// normalized like route prefixes, without a trailing `/` or `/*`
+    path: Option<String>,
+    methods: Vec<String>,
+    content_types: Vec<String>,
+    action: OverrideAction,
+    ttl_secs: Option<u64>,
+}
+
+impl OverrideRule {
+    pub fn validate(&self, index: usize, errors: &mut Vec<String>) {
+        let field = format!("cache.overrides[{}]", index);
+        match (self.action, self.ttl_secs) {
+            (OverrideAction::Force | OverrideAction::Cap, None) => errors.push(format!("{}.ttl_secs : required for force and cap", field)),
+            (OverrideAction::Never, Some(_)) => errors.push(format!("{}.ttl_secs : never does not take a ttl", field)),
+            _ => {}
+        }
+        if let Some(path) = &self.path {
+            if !path.starts_with('/') || path.trim_end_matches('*').contains('*') {
+                errors.push(format!("{}.path : expected a path like /api or /api/* but found {:?}", field, path));
+            }
+        }
+        for pattern in &self.content_types {
+            if !pattern.contains('/') {
+                errors.push(format!("{}.content_types : expected a media type like text/html or image/* but found {:?}", field, pattern));
+            }
+        }
+    }
+}
+
+impl Overrides {
+    pub fn from_config(rules: &[OverrideRule]) -> Self {
+        let rules = rules
+            .iter()
+            .enumerate()
+            .map(|(index, rule)| CompiledRule {
+                name: rule.name.clone().unwrap_or_else(|| format!("cache.overrides[{}]", index)),
+                host: rule.host.as_deref().map(HostPattern::parse),
+                path: rule.path.as_ref().map(|path| path.trim_end_matches('*').trim_end_matches('/').to_string()),
+                methods: rule.methods.iter().map(|method| method.to_ascii_uppercase()).collect(),
+                content_types: rule.content_types.clone(),
+                action: rule.action,
+                ttl_secs: rule.ttl_secs,
+            })
+            .collect();
+        Overrides { rules }
+    }
+
+    /// the override for a response to `method host path`, `None` leaves it to the origin headers
+    pub fn find(&self, host: &str, path: &str, method: &str, headers: &HeaderMap) -> Option<Override> {
+        let host = host.split(':').next().unwrap_or(host).trim_end_matches('.').to_ascii_lowercase();
+        let content_type = headers.get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok());
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(&host, path, method, content_type))
+            .map(|rule| Override { rule: rule.name.clone(), action: rule.action, ttl_secs: rule.ttl_secs })
+    }
+}
+
+impl CompiledRule {
+    fn matches(&self, host: &str, path: &str, method: &str, content_type: Option<&str>) -> bool {
+        if self.host.as_ref().is_some_and(|pattern| !pattern.matches(host)) {
+            return false;
+        }
+        if let Some(prefix) = &self.path {
+            match path.strip_prefix(prefix.as_str()) {
+                Some(rest) if rest.is_empty() || rest.starts_with('/') => {}
+                _ => return false,
+            }
+        }
+        if !(self.methods.is_empty() || self.methods.iter().any(|allowed| allowed.eq_ignore_ascii_case(method))) {
+            return false;
+        }
+        self.content_types.is_empty() || content_type.is_some_and(|content_type| self.content_types.iter().any(|pattern| media_type_matches(pattern, content_type)))
+    }
+}
+
+/// the policy of the backend with the lifetime an override rule forces, caps or takes away
+pub struct OverriddenPolicy {
+    inner: Box<dyn Policy>,
+    cache_control: CacheControl,
+    storable: bool,
+    overridden: Override,
+}
+
+/// wraps `policy` when an override applies to the response, `status` and `headers` are the stored ones
+pub fn apply(policy: Box<dyn Policy>, overridden: Option<&Override>, status: StatusCode, headers: &HeaderMap) -> Box<dyn Policy> {
+    let Some(overridden) = overridden else {
+        return policy;
+    };
+    let cache_control = CacheControl::from_headers(headers);
+    // a forced ttl replaces missing or short freshness, not the origin saying the response is not shareable
+    let storable = !(cache_control.no_store || cache_control.private) && vary_names(headers).is_some() && UNDERSTOOD.contains(&status.as_u16());
+    Box::new(OverriddenPolicy { inner: policy, cache_control, storable, overridden: overridden.clone() })
+}
+
+impl OverriddenPolicy {
+    fn ttl(&self) -> Duration {
+        Duration::from_secs(self.overridden.ttl_secs.unwrap_or(0))
+    }
+
+    fn within_stale_window(&self, time_when_cached: SystemTime, window: Option<u64>) -> bool {
+        let Some(window) = window else {
+            return false;
+        };
+        if !self.allows_stale() {
+            return false;
+        }
+        let lifetime = self.lifetime(time_when_cached);
+        let age = self.age(time_when_cached);
+        age >= lifetime && age < lifetime + Duration::from_secs(window)
+    }
+}
+
+impl Policy for OverriddenPolicy {
+    fn is_cacheable(&self) -> bool {
+        let now = SystemTime::now();
+        match self.overridden.action {
+            OverrideAction::Force => self.storable && self.ttl() > self.inner.age(now),
+            OverrideAction::Cap => self.inner.is_cacheable() && (self.cache_control.no_cache || self.lifetime(now) > self.inner.age(now)),
+            OverrideAction::Never => false,
+        }
+    }
+
+    fn is_stale(&self, time_when_cached: SystemTime) -> bool {
+        let expired = self.age(time_when_cached) >= self.lifetime(time_when_cached);
+        match self.overridden.action {
+            OverrideAction::Force => expired,
+            OverrideAction::Cap => self.inner.is_stale(time_when_cached) || expired,
+            OverrideAction::Never => true,
+        }
+    }
+
+    fn can_serve_while_revalidating(&self, time_when_cached: SystemTime) -> bool {
+        self.within_stale_window(time_when_cached, self.cache_control.stale_while_revalidate)
+    }
+
+    fn can_serve_on_error(&self, time_when_cached: SystemTime, default_window: Option<u64>) -> bool {
+        self.within_stale_window(time_when_cached, self.cache_control.stale_if_error.or(default_window))
+    }
+
+    fn lifetime(&self, time_when_cached: SystemTime) -> Duration {
+        match self.overridden.action {
+            OverrideAction::Force => self.ttl(),
+            OverrideAction::Cap => self.inner.lifetime(time_when_cached).min(self.ttl()),
+            OverrideAction::Never => Duration::ZERO,
+        }
+    }
+
+    fn age(&self, time_when_cached: SystemTime) -> Duration {
+        self.inner.age(time_when_cached)
+    }
+
+    /// a forced ttl ignores `no-cache`, only the revalidate directives keep it from going stale
+    fn allows_stale(&self) -> bool {
+        match self.overridden.action {
+            OverrideAction::Force => !(self.cache_control.must_revalidate || self.cache_control.proxy_revalidate),
+            OverrideAction::Cap => self.inner.allows_stale(),
+            OverrideAction::Never => false,
+        }
+    }
+
+    fn state(&self) -> Option<String> {
+        self.inner.state()
+    }
+}
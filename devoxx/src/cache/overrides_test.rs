@@ -0,0 +1,80 @@
+#[cfg(test)]
+mod overrides_test {
+    use std::time::{Duration, SystemTime};
+
+    use axum::{body::Bytes, http::{HeaderMap, StatusCode}};
+
+    use crate::cache::{
+        cache_util::CachedResponse,
+        freshness::Defaults,
+        overrides::{Override, OverrideAction, Overrides},
+        policy::PolicyBackend,
+        test_util::headers,
+    };
+    use crate::config::config::Config;
+
+    fn overrides(toml: &str) -> Overrides {
+        let config = Config::from_toml(toml).unwrap();
+        assert!(config.validate().is_ok());
+        Overrides::from_config(&config.cache.overrides)
+    }
+
+    fn entry(response: HeaderMap, age: u64, action: OverrideAction, ttl_secs: Option<u64>) -> CachedResponse {
+        let cached_at = SystemTime::now() - Duration::from_secs(age);
+        let ttl_override = Override { rule: "test".to_string(), action, ttl_secs };
+        CachedResponse::new(StatusCode::OK, response, Bytes::from_static(b"body"), cached_at).with_override(Some(ttl_override))
+    }
+
+    #[test]
+    fn test_first_matching_rule_wins() {
+        let rules = overrides(
+            "[[cache.overrides]]\nname = \"api json\"\nhost = \"*.example.com\"\npath = \"/api/*\"\nmethods = [\"get\"]\ncontent_types = [\"application/json\"]\naction = \"force\"\nttl_secs = 60\n\
+             [[cache.overrides]]\npath = \"/api\"\naction = \"never\"\n",
+        );
+        let json = headers(&[("content-type", "application/json; charset=utf-8")]);
+        let found = rules.find("shop.example.com:443", "/api/users", "GET", &json).unwrap();
+        assert_eq!(found, Override { rule: "api json".to_string(), action: OverrideAction::Force, ttl_secs: Some(60) });
+        let html = headers(&[("content-type", "text/html")]);
+        let found = rules.find("shop.example.com", "/api/users", "GET", &html).unwrap();
+        assert_eq!((found.rule.as_str(), found.action), ("cache.overrides[1]", OverrideAction::Never));
+        assert_eq!(rules.find("example.com", "/apix", "GET", &json), None);
+    }
+
+    #[test]
+    fn test_force_caches_responses_without_freshness() {
+        for backend in [PolicyBackend::Simple, PolicyBackend::HttpCacheSemantics] {
+            let plain = entry(HeaderMap::new(), 10, OverrideAction::Force, Some(60));
+            let policy = backend.for_entry(&plain, Defaults::default());
+            assert!(policy.is_cacheable(), "{:?}", backend);
+            assert!(!policy.is_stale(plain.cached_at), "{:?}", backend);
+            assert_eq!(policy.lifetime(plain.cached_at), Duration::from_secs(60), "{:?}", backend);
+
+            let no_cache = entry(headers(&[("cache-control", "no-cache")]), 10, OverrideAction::Force, Some(60));
+            assert!(!backend.for_entry(&no_cache, Defaults::default()).is_stale(no_cache.cached_at), "{:?}", backend);
+            let private = entry(headers(&[("cache-control", "private")]), 0, OverrideAction::Force, Some(60));
+            assert!(!backend.for_entry(&private, Defaults::default()).is_cacheable(), "{:?}", backend);
+        }
+    }
+
+    #[test]
+    fn test_cap_and_never() {
+        let capped = entry(headers(&[("cache-control", "max-age=3600")]), 90, OverrideAction::Cap, Some(60));
+        let policy = PolicyBackend::Simple.for_entry(&capped, Defaults::default());
+        assert_eq!(policy.lifetime(capped.cached_at), Duration::from_secs(60));
+        assert!(policy.is_stale(capped.cached_at));
+        let short = entry(headers(&[("cache-control", "max-age=30")]), 0, OverrideAction::Cap, Some(60));
+        assert_eq!(PolicyBackend::Simple.for_entry(&short, Defaults::default()).lifetime(short.cached_at), Duration::from_secs(30));
+
+        let never = entry(headers(&[("cache-control", "max-age=3600")]), 0, OverrideAction::Never, None);
+        assert!(!PolicyBackend::Simple.for_entry(&never, Defaults::default()).is_cacheable());
+    }
+
+    #[test]
+    fn test_rule_validation() {
+        let config = Config::from_toml("[[cache.overrides]]\naction = \"force\"\n[[cache.overrides]]\npath = \"api\"\naction = \"never\"\nttl_secs = 5\n").unwrap();
+        let err = config.validate().unwrap_err();
+        assert!(err.contains("cache.overrides[0].ttl_secs"), "{}", err);
+        assert!(err.contains("cache.overrides[1].ttl_secs"), "{}", err);
+        assert!(err.contains("cache.overrides[1].path"), "{}", err);
+    }
+}
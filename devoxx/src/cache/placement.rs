@@ -0,0 +1,113 @@
+use std::time::Duration;
+
+use axum::http::{header, HeaderMap};
+use serde::Deserialize;
+
+use super::status::Tier;
+
+/// when one tier takes a response, `[cache.placement.memory]`, `.redis` and `.sqlite`
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TierRule {
+    pub enabled: bool,
+    /// shortest freshness lifetime the tier keeps
+    pub min_ttl_secs: u64,
+    /// longest freshness lifetime the tier keeps, unlimited when not set
+    pub max_ttl_secs: Option<u64>,
+    /// largest body the tier keeps, unlimited when not set
+    pub max_body_bytes: Option<u64>,
+    /// media types such as `application/json` or `image/*`, empty takes every type
+    pub content_types: Vec<String>,
+}
+
+/// declarative placement of cacheable responses on the tiers, `[cache.placement]`
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlacementConfig {
+    pub memory: TierRule,
+    pub redis: TierRule,
+    pub sqlite: TierRule,
+}
+
+/// the tiers that receive one response
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Placement {
+    pub memory: bool,
+    pub redis: bool,
+    pub sqlite: bool,
+}
+
+impl Default for TierRule {
+    fn default() -> Self {
+        TierRule { enabled: true, min_ttl_secs: 0, max_ttl_secs: None, max_body_bytes: None, content_types: Vec::new() }
+    }
+}
+
+impl Default for PlacementConfig {
+    /// every tier takes everything except sqlite, which only keeps what lives for an hour
+    fn default() -> Self {
+        let sqlite = TierRule { min_ttl_secs: 3600, ..TierRule::default() };
+        PlacementConfig { memory: TierRule::default(), redis: TierRule::default(), sqlite }
+    }
+}
+
+impl TierRule {
+    pub fn admits(&self, lifetime: Duration, body_len: usize, content_type: Option<&str>) -> bool {
+        if !self.enabled || lifetime < Duration::from_secs(self.min_ttl_secs) {
+            return false;
+        }
+        if matches!(self.max_ttl_secs, Some(max) if lifetime > Duration::from_secs(max)) {
+            return false;
+        }
+        if matches!(self.max_body_bytes, Some(max) if body_len as u64 > max) {
+            return false;
+        }
+        self.content_types.is_empty() || content_type.is_some_and(|content_type| self.content_types.iter().any(|pattern| media_type_matches(pattern, content_type)))
+    }
+
+    pub fn validate(&self, name: &str, errors: &mut Vec<String>) {
+        if matches!(self.max_ttl_secs, Some(max) if max < self.min_ttl_secs) {
+            errors.push(format!("cache.placement.{}.max_ttl_secs : must not be below min_ttl_secs", name));
+        }
+        for pattern in &self.content_types {
+            if !pattern.contains('/') {
+                errors.push(format!("cache.placement.{}.content_types : expected a media type like text/html or image/* but found {:?}", name, pattern));
+            }
+        }
+    }
+}
+
+impl PlacementConfig {
+    /// tiers a cacheable response with this freshness lifetime goes to, `route_tiers` narrows them
+    /// down for routes that list their own
+    pub fn place(&self, route_tiers: Option<&[Tier]>, lifetime: Duration, body_len: usize, headers: &HeaderMap) -> Placement {
+        let content_type = headers.get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok());
+        let allowed = |tier: Tier| route_tiers.is_none_or(|tiers| tiers.contains(&tier));
+        Placement {
+            memory: allowed(Tier::Memory) && self.memory.admits(lifetime, body_len, content_type),
+            redis: allowed(Tier::Redis) && self.redis.admits(lifetime, body_len, content_type),
+            sqlite: allowed(Tier::Sqlite) && self.sqlite.admits(lifetime, body_len, content_type),
+        }
+    }
+
+    pub fn validate(&self, errors: &mut Vec<String>) {
+        self.memory.validate("memory", errors);
+        self.redis.validate("redis", errors);
+        self.sqlite.validate("sqlite", errors);
+    }
+}
+
+impl Placement {
+    pub fn any(&self) -> bool {
+        self.memory || self.redis || self.sqlite
+    }
+}
+
+/// `pattern` is a full media type or `type/*`, parameters of the header value are ignored
+pub fn media_type_matches(pattern: &str, content_type: &str) -> bool {
+    let media_type = content_type.split(';').next().unwrap_or("").trim();
+    match pattern.strip_suffix("/*") {
+        Some(kind) => media_type.split('/').next().is_some_and(|main| main.eq_ignore_ascii_case(kind)),
+        None => media_type.eq_ignore_ascii_case(pattern),
+    }
+}
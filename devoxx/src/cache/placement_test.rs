@@ -0,0 +1,60 @@
+#[cfg(test)]
+mod placement_test {
+    use std::time::Duration;
+
+    use axum::http::{HeaderMap, HeaderValue};
+
+    use crate::cache::{placement::{Placement, PlacementConfig, TierRule}, status::Tier};
+    use crate::config::config::Config;
+
+    fn content_type(value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn test_default_keeps_short_lived_off_disk() {
+        let placement = PlacementConfig::default();
+        let headers = HeaderMap::new();
+        assert_eq!(placement.place(None, Duration::from_secs(60), 10, &headers), Placement { memory: true, redis: true, sqlite: false });
+        assert_eq!(placement.place(None, Duration::from_secs(3600), 10, &headers), Placement { memory: true, redis: true, sqlite: true });
+    }
+
+    #[test]
+    fn test_ttl_size_and_content_type_limits() {
+        let rule = TierRule { max_ttl_secs: Some(300), max_body_bytes: Some(1024), content_types: vec!["application/json".to_string(), "image/*".to_string()], ..TierRule::default() };
+        assert!(rule.admits(Duration::from_secs(60), 1024, Some("application/json; charset=utf-8")));
+        assert!(rule.admits(Duration::from_secs(60), 10, Some("IMAGE/png")));
+        assert!(!rule.admits(Duration::from_secs(301), 10, Some("image/png")));
+        assert!(!rule.admits(Duration::from_secs(60), 1025, Some("image/png")));
+        assert!(!rule.admits(Duration::from_secs(60), 10, Some("text/html")));
+        assert!(!rule.admits(Duration::from_secs(60), 10, None));
+        assert!(!TierRule { enabled: false, ..TierRule::default() }.admits(Duration::from_secs(60), 10, None));
+    }
+
+    #[test]
+    fn test_route_narrows_tiers() {
+        let placement = PlacementConfig::default();
+        let placed = placement.place(Some(&[Tier::Memory]), Duration::from_secs(7200), 10, &content_type("text/html"));
+        assert_eq!(placed, Placement { memory: true, redis: false, sqlite: false });
+        assert!(!placement.place(Some(&[]), Duration::from_secs(7200), 10, &content_type("text/html")).any());
+    }
+
+    #[test]
+    fn test_placement_from_config() {
+        let config = Config::from_toml("[cache.placement.memory]\nmax_body_bytes = 65536\n[cache.placement.sqlite]\nmin_ttl_secs = 600\ncontent_types = [\"text/*\"]\n").unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.cache.placement.memory.max_body_bytes, Some(65536));
+        assert!(config.cache.placement.redis.enabled);
+        assert!(config.cache.placement.sqlite.admits(Duration::from_secs(600), 10, Some("text/css")));
+
+        let config = Config::from_toml("[cache.placement.redis]\nmin_ttl_secs = 60\nmax_ttl_secs = 30\ncontent_types = [\"json\"]\n").unwrap();
+        let err = config.validate().unwrap_err();
+        assert!(err.contains("cache.placement.redis.max_ttl_secs"), "{}", err);
+        assert!(err.contains("cache.placement.redis.content_types"), "{}", err);
+
+        let config = Config::from_toml("[[vhosts]]\nhost = \"*\"\norigin = \"a:1\"\n[[vhosts.routes]]\nprefix = \"/api\"\ncache = { tiers = [\"memory\", \"origin\"] }\n").unwrap();
+        assert!(config.validate().unwrap_err().contains("vhosts[0].routes[0].cache.tiers"));
+    }
+}
@@ -0,0 +1,218 @@
+use std::time::{Duration, SystemTime};
+
+use axum::http::{HeaderMap, Method, StatusCode, Uri};
+use http_cache_semantics::{CacheOptions, CachePolicy as SemanticsPolicy};
+use serde::Deserialize;
+
+use super::{overrides, cache_control::CacheControl, cache_util::{CacheKey, CachedResponse}, freshness::{negatively_cached, Defaults}, policy_util::CachePolicy};
+
+/// cache decisions for one response, `time_when_cached` is when the tier stored it
+pub trait Policy: Send {
+    /// a shared cache may store the response
+    fn is_cacheable(&self) -> bool;
+    fn is_stale(&self, time_when_cached: SystemTime) -> bool;
+    fn can_serve_while_revalidating(&self, time_when_cached: SystemTime) -> bool;
+    fn can_serve_on_error(&self, time_when_cached: SystemTime, default_window: Option<u64>) -> bool;
+    /// freshness lifetime, zero when the response has none
+    fn lifetime(&self, time_when_cached: SystemTime) -> Duration;
+    fn age(&self, time_when_cached: SystemTime) -> Duration;
+    /// whether the response may ever be served stale, for example to a client sending `max-stale`
+    fn allows_stale(&self) -> bool;
+    /// state persisted with the entry so later decisions do not depend on the tier it came from
+    fn state(&self) -> Option<String>;
+}
+
+/// which policy engine decides what is cached, `[cache] policy` in the config
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PolicyBackend {
+    /// the hand-written `policy_util::CachePolicy`
+    #[default]
+    Simple,
+    /// `http_cache_semantics::CachePolicy`, which also looks at the request
+    HttpCacheSemantics,
+}
+
+impl PolicyBackend {
+    /// policy of a response that just came from the origin. the crate never stores 5xx responses,
+    /// so negatively cached statuses always go through the simple policy
+    pub fn for_response(
+        self,
+        key: &CacheKey,
+        req_headers: &HeaderMap,
+        status: StatusCode,
+        headers: &HeaderMap,
+        response_time: SystemTime,
+        defaults: Defaults,
+    ) -> Box<dyn Policy> {
+        match self {
+            PolicyBackend::HttpCacheSemantics if !negatively_cached(status, defaults) => {
+                Box::new(HttpSemanticsPolicy::new(key, req_headers, status, headers, response_time, defaults))
+            }
+            _ => Box::new(CachePolicy::new(headers.clone()).with_status(status).with_defaults(defaults)),
+        }
+    }
+
+    /// policy of a stored entry, restored from its persisted state when the backend wrote one.
+    /// entries without state, or with state from the other backend, are judged by their headers.
+    /// the override recorded with the entry is applied on top
+    pub fn for_entry(self, cached: &CachedResponse, defaults: Defaults) -> Box<dyn Policy> {
+        let restored = match self {
+            PolicyBackend::HttpCacheSemantics => cached.policy.as_deref().and_then(|state| HttpSemanticsPolicy::restore(state, &cached.headers)),
+            PolicyBackend::Simple => None,
+        };
+        let policy: Box<dyn Policy> = match restored {
+            Some(policy) => Box::new(policy),
+            None => {
+                let key = CacheKey::new(Method::GET, Uri::from_static("/"));
+                self.for_response(&key, &HeaderMap::new(), cached.status, &cached.headers, cached.cached_at, defaults)
+            }
+        };
+        overrides::apply(policy, cached.ttl_override.as_ref(), cached.status, &cached.headers)
+    }
+}
+
+impl Policy for CachePolicy {
+    fn is_cacheable(&self) -> bool {
+        CachePolicy::is_cacheable(self)
+    }
+
+    fn is_stale(&self, time_when_cached: SystemTime) -> bool {
+        CachePolicy::is_stale(self, time_when_cached)
+    }
+
+    fn can_serve_while_revalidating(&self, time_when_cached: SystemTime) -> bool {
+        CachePolicy::can_serve_while_revalidating(self, time_when_cached)
+    }
+
+    fn can_serve_on_error(&self, time_when_cached: SystemTime, default_window: Option<u64>) -> bool {
+        CachePolicy::can_serve_on_error(self, time_when_cached, default_window)
+    }
+
+    fn lifetime(&self, time_when_cached: SystemTime) -> Duration {
+        self.freshness_lifetime(time_when_cached).unwrap_or(Duration::ZERO)
+    }
+
+    fn age(&self, time_when_cached: SystemTime) -> Duration {
+        self.current_age(time_when_cached)
+    }
+
+    fn allows_stale(&self) -> bool {
+        CachePolicy::allows_stale(self)
+    }
+
+    /// everything the simple policy needs is in the stored headers
+    fn state(&self) -> Option<String> {
+        None
+    }
+}
+
+/// `http_cache_semantics::CachePolicy` behind the `Policy` trait. the crate has no notion of the
+/// stale-* extensions, those windows are measured from its lifetime with our own directive parser
+pub struct HttpSemanticsPolicy {
+    inner: SemanticsPolicy,
+    cache_control: CacheControl,
+    response_time: SystemTime,
+}
+
+impl HttpSemanticsPolicy {
+    pub fn new(
+        key: &CacheKey,
+        req_headers: &HeaderMap,
+        status: StatusCode,
+        headers: &HeaderMap,
+        response_time: SystemTime,
+        defaults: Defaults,
+    ) -> Self {
+        let CacheKey(method, uri, _) = key;
+        let mut request = http::Request::builder()
+            .method(method.as_str())
+            .uri(uri.to_string())
+            .body(())
+            .expect("method and uri come from a parsed request");
+        *request.headers_mut() = to_http_headers(req_headers);
+        let mut response = http::Response::builder()
+            .status(status.as_u16())
+            .body(())
+            .expect("status comes from a parsed response");
+        *response.headers_mut() = to_http_headers(headers);
+        // the crate always applies a heuristic, a fraction of zero turns it off
+        let options = CacheOptions { cache_heuristic: defaults.heuristic.map_or(0.0, |heuristic| heuristic.fraction as f32), ..CacheOptions::default() };
+        let inner = SemanticsPolicy::new_options(&request, &response, response_time, options);
+        HttpSemanticsPolicy { inner, cache_control: CacheControl::from_headers(headers), response_time }
+    }
+
+    /// the policy persisted by `state` for a response with these headers, `None` when the state
+    /// was written by another version
+    pub fn restore(state: &str, headers: &HeaderMap) -> Option<Self> {
+        let (response_time, inner): (SystemTime, SemanticsPolicy) = serde_json::from_str(state).ok()?;
+        let cache_control = CacheControl::from_headers(headers);
+        Some(HttpSemanticsPolicy { inner, cache_control, response_time })
+    }
+
+    /// freshness lifetime counted from the response time, the crate only reports what is left of it
+    fn lifetime(&self) -> Duration {
+        self.inner.time_to_live(self.response_time) + self.inner.age(self.response_time)
+    }
+
+    fn within_stale_window(&self, window: Option<u64>) -> bool {
+        let Some(window) = window else {
+            return false;
+        };
+        if !Policy::allows_stale(self) {
+            return false;
+        }
+        let lifetime = self.lifetime();
+        let age = self.inner.age(SystemTime::now());
+        age >= lifetime && age < lifetime + Duration::from_secs(window)
+    }
+}
+
+impl Policy for HttpSemanticsPolicy {
+    fn is_cacheable(&self) -> bool {
+        // no-cache responses are kept for revalidation like the simple policy does
+        self.inner.is_storable() && (self.cache_control.no_cache || !self.inner.is_stale(SystemTime::now()))
+    }
+
+    /// the crate keeps its own response time, `time_when_cached` is only used by the simple policy
+    fn is_stale(&self, _time_when_cached: SystemTime) -> bool {
+        self.inner.is_stale(SystemTime::now())
+    }
+
+    fn can_serve_while_revalidating(&self, _time_when_cached: SystemTime) -> bool {
+        self.within_stale_window(self.cache_control.stale_while_revalidate)
+    }
+
+    fn can_serve_on_error(&self, _time_when_cached: SystemTime, default_window: Option<u64>) -> bool {
+        self.within_stale_window(self.cache_control.stale_if_error.or(default_window))
+    }
+
+    fn lifetime(&self, _time_when_cached: SystemTime) -> Duration {
+        HttpSemanticsPolicy::lifetime(self)
+    }
+
+    fn age(&self, _time_when_cached: SystemTime) -> Duration {
+        self.inner.age(SystemTime::now())
+    }
+
+    fn allows_stale(&self) -> bool {
+        self.cache_control.allows_stale() && self.inner.is_storable()
+    }
+
+    fn state(&self) -> Option<String> {
+        serde_json::to_string(&(self.response_time, &self.inner)).ok()
+    }
+}
+
+/// axum is still on http 0.2 while http-cache-semantics uses http 1
+fn to_http_headers(headers: &HeaderMap) -> http::HeaderMap {
+    let mut converted = http::HeaderMap::with_capacity(headers.len());
+    for (name, value) in headers {
+        if let (Ok(name), Ok(value)) =
+            (http::HeaderName::from_bytes(name.as_str().as_bytes()), http::HeaderValue::from_bytes(value.as_bytes()))
+        {
+            converted.append(name, value);
+        }
+    }
+    converted
+}
@@ -0,0 +1,85 @@
+#[cfg(test)]
+mod policy_test {
+    use std::time::{Duration, SystemTime};
+
+    use axum::{body::Bytes, http::{HeaderMap, Method, StatusCode, Uri}};
+
+    use crate::cache::{cache_util::{CacheKey, CachedResponse}, freshness::Defaults, policy::PolicyBackend, test_util::headers};
+    use crate::config::config::Config;
+
+    const BACKENDS: [PolicyBackend; 2] = [PolicyBackend::Simple, PolicyBackend::HttpCacheSemantics];
+
+    fn key() -> CacheKey {
+        CacheKey::new(Method::GET, Uri::from_static("http://localhost:3000/fast"))
+    }
+
+    fn stored(backend: PolicyBackend, cache_control: &'static str, age: u64) -> CachedResponse {
+        let response = headers(&[("cache-control", cache_control)]);
+        let cached_at = SystemTime::now() - Duration::from_secs(age);
+        let policy = backend.for_response(&key(), &HeaderMap::new(), StatusCode::OK, &response, cached_at, Defaults::default());
+        CachedResponse::new(StatusCode::OK, response, Bytes::from_static(b"fast"), cached_at).with_policy(policy.state())
+    }
+
+    #[test]
+    fn test_backends_agree_on_plain_responses() {
+        for backend in BACKENDS {
+            let fresh = stored(backend, "max-age=7200", 10);
+            let policy = backend.for_entry(&fresh, Defaults::default());
+            assert!(policy.is_cacheable(), "{:?}", backend);
+            assert_eq!(policy.lifetime(fresh.cached_at), Duration::from_secs(7200), "{:?}", backend);
+            assert!(!policy.is_stale(fresh.cached_at), "{:?}", backend);
+
+            let expired = stored(backend, "max-age=60, stale-while-revalidate=60, stale-if-error=600", 90);
+            let policy = backend.for_entry(&expired, Defaults::default());
+            assert!(policy.is_stale(expired.cached_at), "{:?}", backend);
+            assert!(policy.can_serve_while_revalidating(expired.cached_at), "{:?}", backend);
+            assert!(policy.can_serve_on_error(expired.cached_at, None), "{:?}", backend);
+
+            assert!(!backend.for_entry(&stored(backend, "no-store", 0), Defaults::default()).is_cacheable(), "{:?}", backend);
+        }
+    }
+
+    #[test]
+    fn test_semantics_state_is_persisted() {
+        assert!(stored(PolicyBackend::Simple, "max-age=60", 0).policy.is_none());
+        let cached = stored(PolicyBackend::HttpCacheSemantics, "max-age=60", 0);
+        assert!(cached.policy.is_some());
+        // the persisted response time wins over the time the tier reports
+        let moved = cached.clone();
+        let moved = CachedResponse { cached_at: SystemTime::now() - Duration::from_secs(3600), ..moved };
+        assert!(!PolicyBackend::HttpCacheSemantics.for_entry(&moved, Defaults::default()).is_stale(moved.cached_at));
+        assert!(PolicyBackend::Simple.for_entry(&moved, Defaults::default()).is_stale(moved.cached_at));
+    }
+
+    #[test]
+    fn test_semantics_looks_at_the_request() {
+        let response = headers(&[("cache-control", "max-age=600")]);
+        let request = headers(&[("authorization", "Bearer token")]);
+        let now = SystemTime::now();
+        assert!(PolicyBackend::Simple.for_response(&key(), &request, StatusCode::OK, &response, now, Defaults::default()).is_cacheable());
+        assert!(!PolicyBackend::HttpCacheSemantics.for_response(&key(), &request, StatusCode::OK, &response, now, Defaults::default()).is_cacheable());
+    }
+
+    #[test]
+    fn test_negative_cache_with_both_backends() {
+        let defaults = Config::default().cache.defaults();
+        let now = SystemTime::now();
+        for backend in [PolicyBackend::Simple, PolicyBackend::HttpCacheSemantics] {
+            let policy = backend.for_response(&key(), &HeaderMap::new(), StatusCode::BAD_GATEWAY, &HeaderMap::new(), now, defaults);
+            assert!(policy.is_cacheable(), "{:?}", backend);
+            assert_eq!(policy.lifetime(now), Duration::from_secs(10), "{:?}", backend);
+            let no_store = headers(&[("cache-control", "no-store")]);
+            assert!(!backend.for_response(&key(), &HeaderMap::new(), StatusCode::NOT_FOUND, &no_store, now, defaults).is_cacheable());
+            let off = Defaults { negative_ttl: None, ..defaults };
+            assert!(!backend.for_response(&key(), &HeaderMap::new(), StatusCode::BAD_GATEWAY, &HeaderMap::new(), now, off).is_cacheable());
+        }
+    }
+
+    #[test]
+    fn test_backend_from_config() {
+        assert_eq!(Config::default().cache.policy, PolicyBackend::Simple);
+        let config = Config::from_toml("[cache]\npolicy = \"http-cache-semantics\"\n").unwrap();
+        assert_eq!(config.cache.policy, PolicyBackend::HttpCacheSemantics);
+        assert!(Config::from_toml("[cache]\npolicy = \"strict\"\n").is_err());
+    }
+}
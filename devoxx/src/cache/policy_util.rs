@@ -1,80 +1,101 @@
-use axum::http::HeaderMap;
-use core::panic;
+
+use axum::http::{HeaderMap, StatusCode};
 use std::time::{Duration, SystemTime};
-pub struct CachePolicy { 
-    pub headers : HeaderMap   
+
+use super::{cache_control::CacheControl, freshness::{current_age, freshness_lifetime, negatively_cached, Defaults, UNDERSTOOD}, vary::vary_names};
+
+pub struct CachePolicy {
+    pub headers : HeaderMap,
+    pub cache_control : CacheControl,
+    pub status : StatusCode,
+    pub defaults : Defaults
 }
 
-impl CachePolicy { 
-    pub fn new(headers : HeaderMap ) -> Self { 
-        CachePolicy{headers}
+impl CachePolicy {
+    pub fn new(headers : HeaderMap ) -> Self {
+        let cache_control = CacheControl::from_headers(&headers);
+        CachePolicy{headers, cache_control, status: StatusCode::OK, defaults: Defaults::default()}
+    }
+
+    pub fn with_status(mut self, status : StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// lifetimes for responses without explicit freshness, the `Last-Modified` heuristic and the
+    /// negative ttl
+    pub fn with_defaults(mut self, defaults : Defaults) -> Self {
+        self.defaults = defaults;
+        self
     }
-    pub fn is_cacheable(&self) -> bool { 
-        let value = self.max_age();
-        if let Some(age ) = value { 
-            if age > 0 { 
-                return true;
-            } else { 
-                return false;
-            }
+
+    /// a shared cache may store the response and it stays fresh for a while
+    pub fn is_cacheable(&self) -> bool {
+        if self.cache_control.no_store || self.cache_control.private {
+            return false;
+        }
+        // `Vary: *` can never match a later request
+        if vary_names(&self.headers).is_none() {
+            return false;
         }
-        false
+        if !(UNDERSTOOD.contains(&self.status.as_u16()) || negatively_cached(self.status, self.defaults)) {
+            return false;
+        }
+        // no-cache responses may be stored but every use has to go back to the origin first
+        if self.cache_control.no_cache {
+            return true;
+        }
+        let now = SystemTime::now();
+        matches!(self.freshness_lifetime(now), Some(lifetime) if lifetime > self.current_age(now))
     }
 
     pub fn is_stale(&self, time_when_cached: SystemTime) -> bool {
-        if let Some(max_age) = self.max_age() {
-            // Calculate the current time
-            let current_time = SystemTime::now();
-            // Calculate the expiration time by adding the maximum age to the time when the resource was cached
-            let expiration_time = time_when_cached + Duration::from_secs(max_age as u64);
-            
-            // Check if the current time is after the expiration time
-            if let Ok(duration_since) = current_time.duration_since(expiration_time) {
-                // If the duration is greater than zero, the resource is stale
-                return duration_since > Duration::from_secs(0);
-            } else {
-                // If duration_since returns an error, the current time is earlier than the expiration time,
-                // so the resource is not stale
-                return false;
-            }
+        if self.cache_control.no_cache {
+            return true;
+        }
+        if let Some(lifetime) = self.freshness_lifetime(time_when_cached) {
+            // the resource is stale once its age, including the age it had at the origin, reaches the lifetime
+            return self.current_age(time_when_cached) >= lifetime;
         }
-        
-        false // Default to not stale if there's no max-age header or error in calculation
+
+        true // without any lifetime the response can never be served without asking the origin
+    }
+
+    /// a stale response still inside its `stale-while-revalidate` window, RFC 5861 section 3
+    pub fn can_serve_while_revalidating(&self, time_when_cached : SystemTime) -> bool {
+        self.within_stale_window(time_when_cached, self.cache_control.stale_while_revalidate)
+    }
+
+    /// a stale response that may stand in for a failing origin, RFC 5861 section 4. `default_window`
+    /// applies when the response has no `stale-if-error` of its own
+    pub fn can_serve_on_error(&self, time_when_cached : SystemTime, default_window : Option<u64>) -> bool {
+        self.within_stale_window(time_when_cached, self.cache_control.stale_if_error.or(default_window))
     }
-    pub fn is_storable_to_disk(&self) -> bool { 
-        if let Some(age) = self.max_age() { 
-            if age >= 3600 { 
-                return true;
-            }
+
+    /// whether the response has been stale for less than `window` seconds, revalidation directives
+    /// forbid serving it stale at all
+    fn within_stale_window(&self, time_when_cached : SystemTime, window : Option<u64>) -> bool {
+        let (Some(window), Some(lifetime)) = (window, self.freshness_lifetime(time_when_cached)) else {
+            return false;
+        };
+        if !self.cache_control.allows_stale() {
             return false;
         }
-        false
+        let age = self.current_age(time_when_cached);
+        age >= lifetime && age < lifetime + Duration::from_secs(window)
     }
-    fn max_age(&self) -> Option<i32> {
-        let cache_control_header = self.headers.get("CACHE-CONTROL");
-        if cache_control_header.is_none() { 
-            return None;
-        }
-        if let Some(cache_control_value) = cache_control_header { 
-            let cache_control_str = cache_control_value.to_str();
-            match cache_control_str { 
-               Ok(val) =>  { 
-                    println!("value_str : {}" , val);
-                    if let Some(value) = val.trim().strip_prefix("max-age=") { 
-                        if let Ok(age) = value.parse::<i32>() { 
-                            return Some(age);
-                        } else { 
-                            return None;
-                        }
-                    } else { 
-                        return None;
-                    }
-                }, 
-                Err(err) =>  {
-                    return None;
-                }
-            }
-        }
-        None
+
+    /// how long the response is fresh for, `time_when_cached` stands in for a missing `Date`
+    pub fn freshness_lifetime(&self, time_when_cached : SystemTime) -> Option<Duration> {
+        freshness_lifetime(self.status, &self.headers, &self.cache_control, time_when_cached, self.defaults)
+    }
+
+    /// stale responses may be served at all, revalidation directives forbid it
+    pub fn allows_stale(&self) -> bool {
+        self.cache_control.allows_stale()
     }
-}
\ No newline at end of file
+
+    pub fn current_age(&self, time_when_cached : SystemTime) -> Duration {
+        current_age(&self.headers, time_when_cached, SystemTime::now())
+    }
+}
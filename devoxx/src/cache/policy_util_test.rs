@@ -0,0 +1,48 @@
+#[cfg(test)]
+mod policy_util_test {
+    use std::time::{Duration, SystemTime};
+
+    use axum::http::HeaderMap;
+
+    use crate::cache::{policy_util::CachePolicy, test_util::policy};
+
+    #[test]
+    fn test_lists_are_cacheable() {
+        assert!(policy("public, max-age=60").is_cacheable());
+        assert!(policy("max-age=60, must-revalidate").is_cacheable());
+        assert!(!policy("max-age=0").is_cacheable());
+        assert!(!CachePolicy::new(HeaderMap::new()).is_cacheable());
+    }
+
+    #[test]
+    fn test_no_store_and_private_win() {
+        assert!(!policy("max-age=600, no-store").is_cacheable());
+        assert!(!policy("private, max-age=600").is_cacheable());
+        assert!(!policy("private, max-age=7200").is_cacheable());
+    }
+
+    #[test]
+    fn test_s_maxage_drives_staleness() {
+        let cached_at = SystemTime::now() - Duration::from_secs(100);
+        assert!(!policy("max-age=60, s-maxage=600").is_stale(cached_at));
+        assert!(policy("max-age=600, s-maxage=60").is_stale(cached_at));
+        assert_eq!(policy("s-maxage=7200").freshness_lifetime(cached_at), Some(Duration::from_secs(7200)));
+    }
+
+    #[test]
+    fn test_no_cache_is_always_stale() {
+        let p = policy("no-cache, max-age=600");
+        assert!(p.is_cacheable());
+        assert!(p.is_stale(SystemTime::now()));
+    }
+
+    #[test]
+    fn test_stale_if_error_window() {
+        let cached_at = SystemTime::now() - Duration::from_secs(100);
+        assert!(policy("max-age=60, stale-if-error=120").can_serve_on_error(cached_at, None));
+        assert!(!policy("max-age=60, stale-if-error=10").can_serve_on_error(cached_at, Some(600)));
+        assert!(!policy("max-age=60").can_serve_on_error(cached_at, None));
+        assert!(policy("max-age=60").can_serve_on_error(cached_at, Some(600)));
+        assert!(!policy("max-age=60, must-revalidate").can_serve_on_error(cached_at, Some(600)));
+    }
+}
@@ -0,0 +1,137 @@
+use axum::{body::Bytes, http::{header, HeaderMap, HeaderValue, StatusCode}};
+
+use super::freshness::header_date;
+
+/// more ranges than this in one request are answered with the full body, RFC 9110 section 14.2
+pub const MAX_RANGES: usize = 32;
+
+/// what a `Range` header asks of a body of known length
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RangeRequest {
+    /// no usable `Range`, the full body is sent
+    Full,
+    /// inclusive byte offsets, in the order the client asked for them
+    Ranges(Vec<(u64, u64)>),
+    /// none of the ranges overlaps the body, a 416
+    Unsatisfiable,
+}
+
+/// parses a `bytes=` range set against a body of `len` bytes, anything that does not parse is
+/// ignored as RFC 9110 section 14.2 allows
+pub fn parse_range(value: &str, len: u64) -> RangeRequest {
+    let Some(set) = value.trim().strip_prefix("bytes=") else {
+        return RangeRequest::Full;
+    };
+    let mut ranges = Vec::new();
+    let mut specs = 0;
+    for spec in set.split(',').map(str::trim).filter(|spec| !spec.is_empty()) {
+        specs += 1;
+        let Some((first, last)) = spec.split_once('-') else {
+            return RangeRequest::Full;
+        };
+        let (first, last) = (first.trim(), last.trim());
+        let range = if first.is_empty() {
+            // suffix range, the last `last` bytes
+            let Ok(suffix) = last.parse::<u64>() else {
+                return RangeRequest::Full;
+            };
+            (suffix > 0 && len > 0).then(|| (len.saturating_sub(suffix), len - 1))
+        } else {
+            let Ok(first) = first.parse::<u64>() else {
+                return RangeRequest::Full;
+            };
+            let last = match last {
+                "" => u64::MAX,
+                last => match last.parse::<u64>() {
+                    Ok(last) if last >= first => last,
+                    _ => return RangeRequest::Full,
+                },
+            };
+            (first < len).then(|| (first, last.min(len - 1)))
+        };
+        ranges.extend(range);
+    }
+    if specs == 0 || specs > MAX_RANGES {
+        return RangeRequest::Full;
+    }
+    if ranges.is_empty() {
+        return RangeRequest::Unsatisfiable;
+    }
+    RangeRequest::Ranges(ranges)
+}
+
+/// `If-Range` lets the range through only while the stored response is the one the client has,
+/// compared by strong `ETag` or by an exact `Last-Modified`
+pub fn if_range_matches(req_headers: &HeaderMap, headers: &HeaderMap) -> bool {
+    let Some(if_range) = req_headers.get(header::IF_RANGE).and_then(|value| value.to_str().ok()) else {
+        return true;
+    };
+    let if_range = if_range.trim();
+    if if_range.starts_with('"') || if_range.starts_with("W/") {
+        let etag = headers.get(header::ETAG).and_then(|value| value.to_str().ok()).map(str::trim);
+        return !if_range.starts_with("W/") && etag.is_some_and(|etag| !etag.starts_with("W/") && etag == if_range);
+    }
+    match (httpdate::parse_http_date(if_range).ok(), header_date(headers, header::LAST_MODIFIED)) {
+        (Some(date), Some(last_modified)) => date == last_modified,
+        _ => false,
+    }
+}
+
+/// answers the `Range` of `req_headers` from a full 200 response, other responses pass untouched
+pub fn apply_range(req_headers: &HeaderMap, status: StatusCode, mut headers: HeaderMap, body: Bytes) -> (StatusCode, HeaderMap, Bytes) {
+    if status != StatusCode::OK {
+        return (status, headers, body);
+    }
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    let Some(range) = req_headers.get(header::RANGE).and_then(|value| value.to_str().ok()) else {
+        return (status, headers, body);
+    };
+    if !if_range_matches(req_headers, &headers) {
+        return (status, headers, body);
+    }
+    let len = body.len() as u64;
+    match parse_range(range, len) {
+        RangeRequest::Full => (status, headers, body),
+        RangeRequest::Unsatisfiable => {
+            headers.remove(header::CONTENT_TYPE);
+            set_length(&mut headers, 0);
+            insert_header(&mut headers, header::CONTENT_RANGE, format!("bytes */{}", len));
+            (StatusCode::RANGE_NOT_SATISFIABLE, headers, Bytes::new())
+        }
+        RangeRequest::Ranges(ranges) if ranges.len() == 1 => {
+            let (first, last) = ranges[0];
+            let part = body.slice(first as usize..=last as usize);
+            set_length(&mut headers, part.len());
+            insert_header(&mut headers, header::CONTENT_RANGE, format!("bytes {}-{}/{}", first, last, len));
+            (StatusCode::PARTIAL_CONTENT, headers, part)
+        }
+        RangeRequest::Ranges(ranges) => {
+            let boundary = uuid::Uuid::new_v4().simple().to_string();
+            let content_type = headers.get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok()).map(str::to_string);
+            let mut multipart = Vec::new();
+            for (first, last) in ranges {
+                multipart.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+                if let Some(content_type) = &content_type {
+                    multipart.extend_from_slice(format!("Content-Type: {}\r\n", content_type).as_bytes());
+                }
+                multipart.extend_from_slice(format!("Content-Range: bytes {}-{}/{}\r\n\r\n", first, last, len).as_bytes());
+                multipart.extend_from_slice(&body[first as usize..=last as usize]);
+                multipart.extend_from_slice(b"\r\n");
+            }
+            multipart.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+            set_length(&mut headers, multipart.len());
+            insert_header(&mut headers, header::CONTENT_TYPE, format!("multipart/byteranges; boundary={}", boundary));
+            (StatusCode::PARTIAL_CONTENT, headers, Bytes::from(multipart))
+        }
+    }
+}
+
+fn set_length(headers: &mut HeaderMap, len: usize) {
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(len));
+}
+
+fn insert_header(headers: &mut HeaderMap, name: header::HeaderName, value: String) {
+    if let Ok(value) = HeaderValue::from_str(&value) {
+        headers.insert(name, value);
+    }
+}
@@ -0,0 +1,74 @@
+#[cfg(test)]
+mod range_test {
+    use axum::{body::Bytes, http::{HeaderMap, StatusCode}};
+
+    use crate::cache::{range::{apply_range, if_range_matches, parse_range, RangeRequest}, test_util::headers};
+
+    fn stored() -> (HeaderMap, Bytes) {
+        let headers = headers(&[("content-type", "text/plain"), ("content-length", "10"), ("etag", "\"v1\"")]);
+        (headers, Bytes::from_static(b"0123456789"))
+    }
+
+    #[test]
+    fn test_parse_range_forms() {
+        assert_eq!(parse_range("bytes=0-4", 10), RangeRequest::Ranges(vec![(0, 4)]));
+        assert_eq!(parse_range("bytes=7-", 10), RangeRequest::Ranges(vec![(7, 9)]));
+        assert_eq!(parse_range("bytes=-3", 10), RangeRequest::Ranges(vec![(7, 9)]));
+        assert_eq!(parse_range("bytes=8-100", 10), RangeRequest::Ranges(vec![(8, 9)]));
+        assert_eq!(parse_range("bytes=0-1, 20-30", 10), RangeRequest::Ranges(vec![(0, 1)]));
+        assert_eq!(parse_range("bytes=10-", 10), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-0", 10), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=5-2", 10), RangeRequest::Full);
+        assert_eq!(parse_range("items=0-1", 10), RangeRequest::Full);
+        assert_eq!(parse_range(&format!("bytes={}", vec!["0-0"; 33].join(",")), 10), RangeRequest::Full);
+    }
+
+    #[test]
+    fn test_single_range_is_a_206() {
+        let (response, body) = stored();
+        let (status, headers, part) = apply_range(&headers(&[("range", "bytes=2-5")]), StatusCode::OK, response, body);
+        assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+        assert_eq!(part, Bytes::from_static(b"2345"));
+        assert_eq!(headers["content-range"], "bytes 2-5/10");
+        assert_eq!(headers["content-length"], "4");
+        assert_eq!(headers["accept-ranges"], "bytes");
+    }
+
+    #[test]
+    fn test_multiple_ranges_are_multipart() {
+        let (response, body) = stored();
+        let (status, headers, part) = apply_range(&headers(&[("range", "bytes=0-1,-2")]), StatusCode::OK, response, body);
+        assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+        let content_type = headers["content-type"].to_str().unwrap();
+        let boundary = content_type.strip_prefix("multipart/byteranges; boundary=").unwrap();
+        let expected = format!(
+            "--{b}\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-1/10\r\n\r\n01\r\n--{b}\r\nContent-Type: text/plain\r\nContent-Range: bytes 8-9/10\r\n\r\n89\r\n--{b}--\r\n",
+            b = boundary
+        );
+        assert_eq!(part, Bytes::from(expected.clone()));
+        assert_eq!(headers["content-length"], expected.len().to_string().as_str());
+    }
+
+    #[test]
+    fn test_unsatisfiable_is_a_416() {
+        let (response, body) = stored();
+        let (status, headers, part) = apply_range(&headers(&[("range", "bytes=50-60")]), StatusCode::OK, response, body);
+        assert_eq!(status, StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(headers["content-range"], "bytes */10");
+        assert!(part.is_empty());
+    }
+
+    #[test]
+    fn test_if_range_and_other_statuses() {
+        let (response, _) = stored();
+        assert!(if_range_matches(&headers(&[("if-range", "\"v1\"")]), &response));
+        assert!(!if_range_matches(&headers(&[("if-range", "\"v0\"")]), &response));
+        assert!(!if_range_matches(&headers(&[("if-range", "W/\"v1\"")]), &response));
+
+        let (response, body) = stored();
+        let stale = headers(&[("range", "bytes=0-1"), ("if-range", "\"v0\"")]);
+        assert_eq!(apply_range(&stale, StatusCode::OK, response, body.clone()).0, StatusCode::OK);
+        let (status, _, full) = apply_range(&headers(&[("range", "bytes=0-1")]), StatusCode::NOT_FOUND, HeaderMap::new(), body.clone());
+        assert_eq!((status, full), (StatusCode::NOT_FOUND, body));
+    }
+}
@@ -0,0 +1,202 @@
+use std::{sync::{atomic::{AtomicU64, Ordering}, Arc}, time::{Duration, Instant}};
+
+use serde::{Deserialize, Serialize};
+use tokio::{sync::watch, task::JoinHandle};
+
+use crate::{config::{config::CacheConfig, reload::SharedSettings}, storage::store::DbStore};
+
+use super::{buffer::Buffer, cache::RemoteCacheStore, cache_util::CachedResponse};
+
+/// the background sweep of expired entries, `[cache.reaper]`
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReaperConfig {
+    pub enabled: bool,
+    /// pause between two sweeps
+    pub interval_secs: u64,
+    /// memory keys per lock, redis keys per `SCAN` step and sqlite rows per query
+    pub batch_size: usize,
+    /// how long an entry is kept after it can no longer be served, so it can still be revalidated
+    /// with `If-None-Match` or `If-Modified-Since`
+    pub grace_secs: u64,
+}
+
+impl Default for ReaperConfig {
+    fn default() -> Self {
+        ReaperConfig { enabled: true, interval_secs: 60, batch_size: 500, grace_secs: 3600 }
+    }
+}
+
+impl ReaperConfig {
+    pub fn validate(&self, errors: &mut Vec<String>) {
+        if self.interval_secs == 0 {
+            errors.push("cache.reaper.interval_secs : must be at least 1".to_string());
+        }
+        if self.batch_size == 0 {
+            errors.push("cache.reaper.batch_size : must be at least 1".to_string());
+        }
+    }
+}
+
+/// an entry can go once it is stale, outside of every window it could still be served stale in
+/// and older than its lifetime plus `grace_secs`
+pub fn is_expired(cached: &CachedResponse, cache_config: &CacheConfig) -> bool {
+    let policy = cache_config.policy.for_entry(cached, cache_config.defaults());
+    if !policy.is_stale(cached.cached_at) {
+        return false;
+    }
+    if policy.can_serve_while_revalidating(cached.cached_at) || policy.can_serve_on_error(cached.cached_at, cache_config.stale_if_error()) {
+        return false;
+    }
+    policy.age(cached.cached_at) >= policy.lifetime(cached.cached_at) + Duration::from_secs(cache_config.reaper.grace_secs)
+}
+
+/// what the reaper did since the process started
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReaperStats {
+    pub sweeps: u64,
+    pub last_sweep_ms: u64,
+    pub memory_removed: u64,
+    pub redis_removed: u64,
+    pub sqlite_removed: u64,
+    pub errors: u64,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    sweeps: AtomicU64,
+    last_sweep_ms: AtomicU64,
+    memory_removed: AtomicU64,
+    redis_removed: AtomicU64,
+    sqlite_removed: AtomicU64,
+    errors: AtomicU64,
+}
+
+/// the tiers a sweep goes over
+#[derive(Debug, Clone)]
+pub struct Tiers {
+    pub memory: Buffer,
+    pub redis: RemoteCacheStore,
+    pub sqlite: DbStore,
+}
+
+/// removes expired entries from every tier, clones share the counters
+#[derive(Debug, Clone, Default)]
+pub struct Reaper {
+    counters: Arc<Counters>,
+}
+
+/// the running reaper task
+pub struct ReaperHandle {
+    stop: watch::Sender<bool>,
+    task: JoinHandle<()>,
+}
+
+impl Reaper {
+    pub fn new() -> Self {
+        Reaper::default()
+    }
+
+    pub fn stats(&self) -> ReaperStats {
+        ReaperStats {
+            sweeps: self.counters.sweeps.load(Ordering::Relaxed),
+            last_sweep_ms: self.counters.last_sweep_ms.load(Ordering::Relaxed),
+            memory_removed: self.counters.memory_removed.load(Ordering::Relaxed),
+            redis_removed: self.counters.redis_removed.load(Ordering::Relaxed),
+            sqlite_removed: self.counters.sqlite_removed.load(Ordering::Relaxed),
+            errors: self.counters.errors.load(Ordering::Relaxed),
+        }
+    }
+
+    /// sweeps every `interval_secs` of the current settings, a reload changes the interval, the
+    /// batch size and whether sweeps run from the next one on
+    pub fn spawn(&self, mut tiers: Tiers, settings: SharedSettings) -> ReaperHandle {
+        let (stop, mut stopped) = watch::channel(false);
+        let reaper = self.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                let interval = Duration::from_secs(settings.current().config.cache.reaper.interval_secs.max(1));
+                tokio::select! {
+                    _ = tokio::time::sleep(interval) => {}
+                    _ = stopped.changed() => break,
+                }
+                let current = settings.current();
+                if current.config.cache.reaper.enabled {
+                    reaper.sweep(&mut tiers, &current.config.cache, &stopped).await;
+                }
+            }
+        });
+        ReaperHandle { stop, task }
+    }
+
+    /// one pass over memory, redis and sqlite, cut short when `stopped` turns true
+    pub async fn sweep(&self, tiers: &mut Tiers, cache_config: &CacheConfig, stopped: &watch::Receiver<bool>) {
+        let started = Instant::now();
+        let batch = cache_config.reaper.batch_size.max(1);
+        let expired = |cached: &CachedResponse| is_expired(cached, cache_config);
+
+        let removed = tiers.memory.reap(batch, expired);
+        self.counters.memory_removed.fetch_add(removed as u64, Ordering::Relaxed);
+
+        if tiers.redis.is_connected() {
+            let mut cursor = 0;
+            loop {
+                match tiers.redis.reap_batch(cursor, batch, expired) {
+                    Ok((next, removed)) => {
+                        self.counters.redis_removed.fetch_add(removed as u64, Ordering::Relaxed);
+                        cursor = next;
+                    }
+                    Err(err) => {
+                        println!("reaper : redis sweep failed : {}", err);
+                        self.counters.errors.fetch_add(1, Ordering::Relaxed);
+                        break;
+                    }
+                }
+                if cursor == 0 || *stopped.borrow() {
+                    break;
+                }
+                tokio::task::yield_now().await;
+            }
+        }
+
+        let mut after = 0;
+        while !*stopped.borrow() {
+            let pages = match tiers.sqlite.page_batch(after, batch).await {
+                Ok(pages) => pages,
+                Err(err) => {
+                    println!("reaper : sqlite sweep failed : {}", err);
+                    self.counters.errors.fetch_add(1, Ordering::Relaxed);
+                    break;
+                }
+            };
+            let Some((last, _)) = pages.last() else {
+                break;
+            };
+            after = *last;
+            let ids: Vec<i64> = pages.iter().filter(|(_, cached)| expired(cached)).map(|(id, _)| *id).collect();
+            match tiers.sqlite.remove_pages(&ids).await {
+                Ok(removed) => {
+                    self.counters.sqlite_removed.fetch_add(removed, Ordering::Relaxed);
+                }
+                Err(err) => {
+                    println!("reaper : sqlite sweep failed : {}", err);
+                    self.counters.errors.fetch_add(1, Ordering::Relaxed);
+                    break;
+                }
+            }
+        }
+
+        self.counters.sweeps.fetch_add(1, Ordering::Relaxed);
+        self.counters.last_sweep_ms.store(started.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+}
+
+impl ReaperHandle {
+    /// stops the task once the batch it is working on is done
+    pub async fn shutdown(self) {
+        let _ = self.stop.send(true);
+        if let Err(err) = self.task.await {
+            println!("reaper : task failed : {}", err);
+        }
+    }
+}
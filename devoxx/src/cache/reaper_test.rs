@@ -0,0 +1,102 @@
+#[cfg(test)]
+mod reaper_test {
+    use std::time::{Duration, SystemTime};
+
+    use axum::{body::Bytes, http::{HeaderMap, HeaderValue, Method, StatusCode, Uri}};
+    use tokio::sync::watch;
+
+    use crate::cache::{
+        buffer::{weight, Buffer},
+        cache::RemoteCacheStore,
+        cache_util::{CacheKey, CachedResponse},
+        eviction::MemoryConfig,
+        reaper::{is_expired, Reaper, Tiers},
+    };
+    use crate::config::config::{CacheConfig, Config};
+    use crate::config::reload::SharedSettings;
+    use crate::storage::store::DbStore;
+
+    fn entry(cache_control: &'static str, age: u64) -> CachedResponse {
+        let mut headers = HeaderMap::new();
+        headers.insert("cache-control", HeaderValue::from_static(cache_control));
+        CachedResponse::new(StatusCode::OK, headers, Bytes::from_static(b"body"), SystemTime::now() - Duration::from_secs(age))
+    }
+
+    fn without_grace() -> CacheConfig {
+        let mut config = CacheConfig::default();
+        config.reaper.grace_secs = 0;
+        config
+    }
+
+    #[test]
+    fn test_expiry_waits_for_stale_windows_and_grace() {
+        let config = without_grace();
+        assert!(!is_expired(&entry("max-age=60", 30), &config));
+        assert!(is_expired(&entry("max-age=60", 90), &config));
+        assert!(!is_expired(&entry("max-age=60, stale-while-revalidate=60", 90), &config));
+        assert!(is_expired(&entry("max-age=60, stale-while-revalidate=60", 150), &config));
+        assert!(!is_expired(&entry("max-age=60", 90), &CacheConfig::default()));
+        assert!(is_expired(&entry("max-age=60", 3700), &CacheConfig::default()));
+    }
+
+    #[test]
+    fn test_memory_reap_drops_expired_variants() {
+        let buffer = Buffer::new(&MemoryConfig::default());
+        let fresh = CacheKey::new(Method::GET, Uri::from_static("http://origin/fresh"));
+        let mixed = CacheKey::new(Method::GET, Uri::from_static("http://origin/mixed"));
+        buffer.insert(fresh.clone(), entry("max-age=60", 0));
+        buffer.insert(mixed.clone(), entry("max-age=60", 120).with_variant("a".to_string()));
+        buffer.insert(mixed.clone(), entry("max-age=600", 120).with_variant("b".to_string()));
+        buffer.insert(CacheKey::new(Method::GET, Uri::from_static("http://origin/old")), entry("max-age=1", 120));
+        let config = without_grace();
+        assert_eq!(buffer.reap(1, |cached| is_expired(cached, &config)), 2);
+        let stats = buffer.stats();
+        assert_eq!(stats.entries, 2);
+        // only variant b of the mixed key is left
+        assert_eq!(stats.bytes, weight(&entry("max-age=60", 0)) + weight(&entry("max-age=600", 0)));
+        assert!(buffer.contains(&fresh, &HeaderMap::new(), ""));
+    }
+
+    #[tokio::test]
+    async fn test_sweep_covers_memory_and_sqlite() {
+        let mut sqlite = DbStore::new("sqlite::memory:".to_string()).await.unwrap();
+        for (path, cache_control, age) in [("/a", "max-age=60", 0), ("/b", "max-age=60", 120), ("/c", "max-age=1", 120)] {
+            let key = CacheKey::new(Method::GET, format!("http://origin{}", path).parse::<Uri>().unwrap());
+            sqlite.add(key, entry(cache_control, age)).await.unwrap();
+        }
+        let memory = Buffer::new(&MemoryConfig::default());
+        memory.insert(CacheKey::new(Method::GET, Uri::from_static("http://origin/b")), entry("max-age=60", 120));
+        let mut tiers = Tiers { memory, redis: RemoteCacheStore::new("redis://127.0.0.1:1".to_string(), 1), sqlite: sqlite.clone() };
+        let mut config = without_grace();
+        config.reaper.batch_size = 1;
+        let reaper = Reaper::new();
+        let (_stop, stopped) = watch::channel(false);
+        reaper.sweep(&mut tiers, &config, &stopped).await;
+
+        let stats = reaper.stats();
+        assert_eq!((stats.sweeps, stats.memory_removed, stats.redis_removed, stats.sqlite_removed, stats.errors), (1, 1, 0, 2, 0));
+        let remaining = sqlite.page_batch(0, 10).await.unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(tiers.memory.stats().entries, 0);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_stops_the_task() {
+        let tiers = Tiers {
+            memory: Buffer::new(&MemoryConfig::default()),
+            redis: RemoteCacheStore::new("redis://127.0.0.1:1".to_string(), 1),
+            sqlite: DbStore::new("sqlite::memory:".to_string()).await.unwrap(),
+        };
+        let handle = Reaper::new().spawn(tiers, SharedSettings::new(Config::default(), None));
+        tokio::time::timeout(Duration::from_secs(1), handle.shutdown()).await.unwrap();
+    }
+
+    #[test]
+    fn test_reaper_config() {
+        let config = Config::from_toml("[cache.reaper]\ninterval_secs = 0\nbatch_size = 0\n").unwrap();
+        let err = config.validate().unwrap_err();
+        assert!(err.contains("cache.reaper.interval_secs"), "{}", err);
+        assert!(err.contains("cache.reaper.batch_size"), "{}", err);
+        assert!(Config::default().cache.reaper.enabled);
+    }
+}
@@ -0,0 +1,36 @@
+use std::{collections::HashSet, sync::{Arc, Mutex}};
+
+use super::cache_util::CacheKey;
+
+/// entries with a background refresh running, a burst of stale hits on one entry starts a single refresh
+#[derive(Debug, Clone, Default)]
+pub struct Refreshing {
+    entries: Arc<Mutex<HashSet<(CacheKey, String)>>>,
+}
+
+impl Refreshing {
+    pub fn new() -> Self {
+        Refreshing::default()
+    }
+
+    /// claims the variant of the key, `None` when it is already being refreshed.
+    /// the claim is released when the guard is dropped, so a failed refresh can be retried
+    pub fn start(&self, key: &CacheKey, variant: &str) -> Option<RefreshGuard> {
+        let entry = (key.clone(), variant.to_string());
+        if !self.entries.lock().unwrap().insert(entry.clone()) {
+            return None;
+        }
+        Some(RefreshGuard { entries: self.entries.clone(), entry })
+    }
+}
+
+pub struct RefreshGuard {
+    entries: Arc<Mutex<HashSet<(CacheKey, String)>>>,
+    entry: (CacheKey, String),
+}
+
+impl Drop for RefreshGuard {
+    fn drop(&mut self) {
+        self.entries.lock().unwrap().remove(&self.entry);
+    }
+}
@@ -0,0 +1,34 @@
+#[cfg(test)]
+mod refresh_test {
+    use axum::http::{Method, Uri};
+
+    use crate::cache::{cache_util::CacheKey, refresh::Refreshing, test_util::{ago, policy}};
+
+    #[test]
+    fn test_stale_while_revalidate_window() {
+        let p = policy("max-age=60, stale-while-revalidate=30");
+        assert!(!p.can_serve_while_revalidating(ago(10)));
+        assert!(p.can_serve_while_revalidating(ago(70)));
+        assert!(!p.can_serve_while_revalidating(ago(100)));
+        assert!(!policy("max-age=60").can_serve_while_revalidating(ago(70)));
+    }
+
+    #[test]
+    fn test_revalidation_directives_forbid_serving_stale() {
+        assert!(!policy("max-age=60, stale-while-revalidate=30, must-revalidate").can_serve_while_revalidating(ago(70)));
+        assert!(!policy("s-maxage=60, stale-while-revalidate=30").can_serve_while_revalidating(ago(70)));
+        assert!(!policy("no-cache, stale-while-revalidate=30").can_serve_while_revalidating(ago(10)));
+    }
+
+    #[test]
+    fn test_one_refresh_per_variant() {
+        let refreshing = Refreshing::new();
+        let key = CacheKey::new(Method::GET, Uri::from_static("http://origin/slow"));
+        let guard = refreshing.start(&key, "").unwrap();
+        assert!(refreshing.start(&key, "").is_none());
+        assert!(refreshing.clone().start(&key, "").is_none());
+        assert!(refreshing.start(&key, "accept-encoding=gzip").is_some());
+        drop(guard);
+        assert!(refreshing.start(&key, "").is_some());
+    }
+}
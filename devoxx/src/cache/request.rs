@@ -0,0 +1,62 @@
+use std::time::{Duration, SystemTime};
+
+use axum::http::{header, HeaderMap};
+
+use super::{cache_control::CacheControl, policy::Policy};
+
+/// what the client asked of the cache, RFC 9111 section 5.2.1
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RequestDirectives {
+    pub cache_control: CacheControl,
+    /// `Pragma: no-cache` from an HTTP/1.0 client, only honored without a `Cache-Control`
+    pub pragma_no_cache: bool,
+}
+
+impl RequestDirectives {
+    pub fn from_headers(req_headers: &HeaderMap) -> Self {
+        let cache_control = CacheControl::from_headers(req_headers);
+        let pragma_no_cache = !req_headers.contains_key(header::CACHE_CONTROL)
+            && req_headers
+                .get_all(header::PRAGMA)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .any(|value| value.split(',').any(|directive| directive.trim().eq_ignore_ascii_case("no-cache")));
+        RequestDirectives { cache_control, pragma_no_cache }
+    }
+
+    /// a stored response may only be used after the origin confirmed it
+    pub fn forces_revalidation(&self) -> bool {
+        self.cache_control.no_cache || self.pragma_no_cache
+    }
+
+    /// neither the request nor the response to it may be stored
+    pub fn bypasses_storage(&self) -> bool {
+        self.cache_control.no_store
+    }
+
+    /// the client wants a stored response or a 504, never a trip to the origin
+    pub fn only_if_cached(&self) -> bool {
+        self.cache_control.only_if_cached
+    }
+
+    /// whether a stored response is acceptable to the client without asking the origin
+    pub fn accepts(&self, policy: &dyn Policy, time_when_cached: SystemTime) -> bool {
+        if self.forces_revalidation() {
+            return false;
+        }
+        let age = policy.age(time_when_cached);
+        let lifetime = policy.lifetime(time_when_cached);
+        if matches!(self.cache_control.max_age, Some(max_age) if age > Duration::from_secs(max_age)) {
+            return false;
+        }
+        if matches!(self.cache_control.min_fresh, Some(min_fresh) if lifetime.saturating_sub(age) < Duration::from_secs(min_fresh)) {
+            return false;
+        }
+        if !policy.is_stale(time_when_cached) {
+            return true;
+        }
+        let staleness = age.saturating_sub(lifetime);
+        policy.allows_stale()
+            && matches!(self.cache_control.max_stale, Some(max_stale) if staleness.as_secs() <= max_stale)
+    }
+}
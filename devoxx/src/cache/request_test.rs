@@ -0,0 +1,53 @@
+#[cfg(test)]
+mod request_test {
+    use crate::cache::{request::RequestDirectives, test_util::{ago, cache_control, headers, policy}};
+
+    fn request(pairs: &[(&'static str, &'static str)]) -> RequestDirectives {
+        RequestDirectives::from_headers(&headers(pairs))
+    }
+
+    #[test]
+    fn test_request_directives_are_parsed() {
+        let cc = cache_control("max-stale, min-fresh=30, only-if-cached");
+        assert_eq!(cc.max_stale, Some(u64::MAX));
+        assert_eq!(cc.min_fresh, Some(30));
+        assert!(cc.only_if_cached);
+        assert_eq!(cache_control("max-stale=20").max_stale, Some(20));
+    }
+
+    #[test]
+    fn test_no_cache_and_pragma_force_revalidation() {
+        let fresh = policy("max-age=600");
+        assert!(request(&[]).accepts(&fresh, ago(10)));
+        assert!(!request(&[("cache-control", "no-cache")]).accepts(&fresh, ago(10)));
+        assert!(!request(&[("pragma", "no-cache")]).accepts(&fresh, ago(10)));
+        // Cache-Control takes over from Pragma
+        assert!(!request(&[("pragma", "no-cache"), ("cache-control", "max-age=60")]).forces_revalidation());
+    }
+
+    #[test]
+    fn test_max_age_and_min_fresh_narrow_freshness() {
+        let fresh = policy("max-age=600");
+        assert!(!request(&[("cache-control", "max-age=5")]).accepts(&fresh, ago(10)));
+        assert!(request(&[("cache-control", "max-age=60")]).accepts(&fresh, ago(10)));
+        assert!(!request(&[("cache-control", "min-fresh=595")]).accepts(&fresh, ago(10)));
+        assert!(request(&[("cache-control", "min-fresh=60")]).accepts(&fresh, ago(10)));
+    }
+
+    #[test]
+    fn test_max_stale_widens_freshness() {
+        let stale = policy("max-age=60");
+        assert!(!request(&[]).accepts(&stale, ago(90)));
+        assert!(request(&[("cache-control", "max-stale=60")]).accepts(&stale, ago(90)));
+        assert!(!request(&[("cache-control", "max-stale=10")]).accepts(&stale, ago(90)));
+        assert!(request(&[("cache-control", "max-stale")]).accepts(&stale, ago(9000)));
+        assert!(!request(&[("cache-control", "max-stale")]).accepts(&policy("max-age=60, must-revalidate"), ago(90)));
+    }
+
+    #[test]
+    fn test_no_store_and_only_if_cached() {
+        assert!(request(&[("cache-control", "no-store")]).bypasses_storage());
+        assert!(request(&[("cache-control", "only-if-cached")]).only_if_cached());
+        assert!(!request(&[]).bypasses_storage());
+    }
+}
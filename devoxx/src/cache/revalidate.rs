@@ -0,0 +1,57 @@
+use std::time::SystemTime;
+
+use axum::http::{header, HeaderMap, HeaderValue};
+
+use super::cache_util::CachedResponse;
+
+/// headers a 304 must not overwrite in the stored response, RFC 9111 section 3.2
+const KEEP_STORED: [header::HeaderName; 5] =
+    [header::CONTENT_LENGTH, header::CONTENT_ENCODING, header::TRANSFER_ENCODING, header::CONNECTION, header::CONTENT_RANGE];
+
+/// whether a stale entry can be revalidated instead of downloaded again
+pub fn has_validators(cached: &CachedResponse) -> bool {
+    cached.headers.contains_key(header::ETAG) || cached.headers.contains_key(header::LAST_MODIFIED)
+}
+
+/// the client request with the validators of the stored response, `None` when it has none.
+/// conditionals the client sent itself are dropped since the proxy answers the client
+pub fn conditional_headers(req_headers: &HeaderMap, cached: &CachedResponse) -> Option<HeaderMap> {
+    if !has_validators(cached) {
+        return None;
+    }
+    let mut headers = req_headers.clone();
+    headers.remove(header::IF_NONE_MATCH);
+    headers.remove(header::IF_MODIFIED_SINCE);
+    headers.remove(header::IF_MATCH);
+    headers.remove(header::IF_UNMODIFIED_SINCE);
+    if let Some(etag) = cached.headers.get(header::ETAG) {
+        headers.insert(header::IF_NONE_MATCH, etag.clone());
+    }
+    if let Some(last_modified) = cached.headers.get(header::LAST_MODIFIED) {
+        headers.insert(header::IF_MODIFIED_SINCE, last_modified.clone());
+    }
+    Some(headers)
+}
+
+/// applies a 304 to the stored response, its headers replace the stored ones and it is fresh again
+pub fn merge_not_modified(cached: &CachedResponse, not_modified: &HeaderMap, now: SystemTime) -> CachedResponse {
+    let mut headers = cached.headers.clone();
+    for name in not_modified.keys() {
+        if KEEP_STORED.contains(name) {
+            continue;
+        }
+        headers.remove(name);
+        for value in not_modified.get_all(name) {
+            headers.append(name.clone(), value.clone());
+        }
+    }
+    CachedResponse::new(cached.status, headers, cached.body.clone(), now).with_variant(cached.variant.clone()).with_override(cached.ttl_override.clone())
+}
+
+/// headers of a stale entry served because the origin failed, the `Cache-Status` side of it is
+/// added with the rest of the trace, see `cache::status`
+pub fn stale_on_error(cached: &CachedResponse) -> HeaderMap {
+    let mut headers = cached.headers.clone();
+    headers.append(header::WARNING, HeaderValue::from_static("111 devoxx \"Revalidation Failed\""));
+    headers
+}
@@ -0,0 +1,57 @@
+#[cfg(test)]
+mod revalidate_test {
+    use std::time::{Duration, SystemTime};
+
+    use axum::{body::Bytes, http::{HeaderMap, HeaderValue, StatusCode}};
+
+    use crate::cache::{cache_util::CachedResponse, revalidate::{conditional_headers, merge_not_modified, stale_on_error}};
+
+    fn stored(headers: &[(&'static str, &'static str)]) -> CachedResponse {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.insert(*name, HeaderValue::from_static(value));
+        }
+        CachedResponse::new(StatusCode::OK, map, Bytes::from_static(b"large page"), SystemTime::now() - Duration::from_secs(600))
+    }
+
+    #[test]
+    fn test_conditional_headers_use_stored_validators() {
+        let cached = stored(&[("etag", "\"v1\""), ("last-modified", "Mon, 08 Apr 2024 12:43:21 GMT")]);
+        let mut req = HeaderMap::new();
+        req.insert("accept", HeaderValue::from_static("text/html"));
+        req.insert("if-none-match", HeaderValue::from_static("\"client\""));
+        let headers = conditional_headers(&req, &cached).unwrap();
+        assert_eq!(headers.get("if-none-match").unwrap(), "\"v1\"");
+        assert_eq!(headers.get("if-modified-since").unwrap(), "Mon, 08 Apr 2024 12:43:21 GMT");
+        assert_eq!(headers.get("accept").unwrap(), "text/html");
+    }
+
+    #[test]
+    fn test_no_validators_no_revalidation() {
+        assert!(conditional_headers(&HeaderMap::new(), &stored(&[("cache-control", "max-age=60")])).is_none());
+    }
+
+    #[test]
+    fn test_not_modified_merges_headers_and_keeps_body() {
+        let cached = stored(&[("etag", "\"v1\""), ("cache-control", "max-age=60"), ("content-length", "10"), ("x-kept", "yes")]);
+        let mut not_modified = HeaderMap::new();
+        not_modified.insert("cache-control", HeaderValue::from_static("max-age=600"));
+        not_modified.insert("content-length", HeaderValue::from_static("0"));
+        let now = SystemTime::now();
+        let refreshed = merge_not_modified(&cached, &not_modified, now);
+        assert_eq!(refreshed.headers.get("cache-control").unwrap(), "max-age=600");
+        assert_eq!(refreshed.headers.get("content-length").unwrap(), "10");
+        assert_eq!(refreshed.headers.get("x-kept").unwrap(), "yes");
+        assert_eq!(refreshed.body, cached.body);
+        assert_eq!(refreshed.cached_at, now);
+        assert_eq!(refreshed.status, StatusCode::OK);
+    }
+
+    #[test]
+    fn test_stale_on_error_is_annotated() {
+        let cached = stored(&[("cache-control", "max-age=60, stale-if-error=600")]);
+        let headers = stale_on_error(&cached);
+        assert_eq!(headers.get("warning").unwrap(), "111 devoxx \"Revalidation Failed\"");
+        assert_eq!(headers.get("cache-control").unwrap(), "max-age=60, stale-if-error=600");
+    }
+}
@@ -0,0 +1,161 @@
+use std::time::{Duration, Instant, SystemTime};
+
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use serde::Deserialize;
+
+use super::policy::Policy;
+
+/// cache name used in `Cache-Status`, RFC 9211 section 2
+pub const CACHE_NAME: &str = "devoxx";
+/// debug header naming the tier that served the response, enabled with `[cache] tier_header`
+pub const TIER_HEADER: &str = "x-devoxx-tier";
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Tier {
+    Memory,
+    Redis,
+    Sqlite,
+    Origin,
+}
+
+impl Tier {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Tier::Memory => "memory",
+            Tier::Redis => "redis",
+            Tier::Sqlite => "sqlite",
+            Tier::Origin => "origin",
+        }
+    }
+}
+
+/// why the request went to the origin, the `fwd` parameter of `Cache-Status`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Forward {
+    /// caching is turned off for the route
+    Bypass,
+    /// the method is not cacheable
+    Method,
+    UriMiss,
+    /// the client asked for a fresh response with `no-cache` or `max-age`
+    Request,
+    /// a stored response was stale and had to be revalidated
+    Stale,
+}
+
+impl Forward {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Forward::Bypass => "bypass",
+            Forward::Method => "method",
+            Forward::UriMiss => "uri-miss",
+            Forward::Request => "request",
+            Forward::Stale => "stale",
+        }
+    }
+}
+
+/// what happened to one request on its way through the tiers, turned into response headers
+#[derive(Debug, Default)]
+pub struct Trace {
+    pub tier: Option<Tier>,
+    pub hit: bool,
+    pub forward: Option<Forward>,
+    pub forward_status: Option<StatusCode>,
+    pub stored: bool,
+    /// seconds the response stays fresh, negative once it is stale
+    pub ttl: Option<i64>,
+    /// age of a response served from a tier, replaces the `Age` it was stored with
+    pub age: Option<u64>,
+    pub detail: Option<&'static str>,
+    timings: Vec<(Tier, Duration)>,
+}
+
+impl Trace {
+    pub fn new() -> Self {
+        Trace::default()
+    }
+
+    /// the response is a stored one, `policy` is the policy of that entry
+    pub fn served_from(&mut self, tier: Tier, policy: &dyn Policy, time_when_cached: SystemTime) {
+        let age = policy.age(time_when_cached);
+        self.tier = Some(tier);
+        self.age = Some(age.as_secs());
+        self.ttl = Some(ttl(policy.lifetime(time_when_cached), age));
+    }
+
+    /// the origin answered with a response that went into the tiers, `ttl` is its lifetime left
+    pub fn stored(&mut self, policy: &dyn Policy, time_when_cached: SystemTime) {
+        self.stored = true;
+        self.ttl = Some(ttl(policy.lifetime(time_when_cached), policy.age(time_when_cached)));
+    }
+
+    /// adds the time since `started` to the tier, lookups of the same tier are summed
+    pub fn record(&mut self, tier: Tier, started: Instant) {
+        let elapsed = started.elapsed();
+        match self.timings.iter_mut().find(|(recorded, _)| *recorded == tier) {
+            Some((_, total)) => *total += elapsed,
+            None => self.timings.push((tier, elapsed)),
+        }
+    }
+
+    /// `Cache-Status` member of this cache, `None` when the request never reached the cache
+    pub fn cache_status(&self) -> Option<String> {
+        if !self.hit && self.forward.is_none() && self.detail.is_none() {
+            return None;
+        }
+        let mut status = CACHE_NAME.to_string();
+        if self.hit {
+            status.push_str("; hit");
+        }
+        if let Some(forward) = self.forward {
+            status.push_str(&format!("; fwd={}", forward.as_str()));
+        }
+        if let Some(forward_status) = self.forward_status {
+            status.push_str(&format!("; fwd-status={}", forward_status.as_u16()));
+        }
+        if let Some(ttl) = self.ttl {
+            status.push_str(&format!("; ttl={}", ttl));
+        }
+        if self.stored {
+            status.push_str("; stored");
+        }
+        if let Some(detail) = self.detail {
+            status.push_str(&format!("; detail={}", detail));
+        }
+        Some(status)
+    }
+
+    /// `Server-Timing` with one metric per consulted tier, durations in milliseconds
+    pub fn server_timing(&self) -> Option<String> {
+        if self.timings.is_empty() {
+            return None;
+        }
+        let metrics: Vec<String> = self
+            .timings
+            .iter()
+            .map(|(tier, elapsed)| format!("{};dur={:.3}", tier.as_str(), elapsed.as_secs_f64() * 1000.0))
+            .collect();
+        Some(metrics.join(", "))
+    }
+
+    pub fn apply(&self, headers: &mut HeaderMap, tier_header: bool) {
+        if let Some(age) = self.age {
+            headers.insert(header::AGE, HeaderValue::from(age));
+        }
+        if let Some(status) = self.cache_status().and_then(|status| HeaderValue::from_str(&status).ok()) {
+            headers.append("cache-status", status);
+        }
+        if let Some(timing) = self.server_timing().and_then(|timing| HeaderValue::from_str(&timing).ok()) {
+            headers.append("server-timing", timing);
+        }
+        if let (true, Some(tier)) = (tier_header, self.tier) {
+            headers.insert(TIER_HEADER, HeaderValue::from_static(tier.as_str()));
+        }
+    }
+}
+
+fn ttl(lifetime: Duration, age: Duration) -> i64 {
+    lifetime.as_secs() as i64 - age.as_secs() as i64
+}
@@ -0,0 +1,59 @@
+#[cfg(test)]
+mod status_test {
+    use std::time::{Duration, Instant, SystemTime};
+
+    use axum::http::{HeaderMap, HeaderValue, StatusCode};
+
+    use crate::cache::{status::{Forward, Tier, Trace, TIER_HEADER}, test_util::policy};
+
+    #[test]
+    fn test_hit_reports_age_and_ttl() {
+        let mut trace = Trace::new();
+        trace.hit = true;
+        trace.served_from(Tier::Redis, &policy("max-age=600"), SystemTime::now() - Duration::from_secs(100));
+        assert_eq!(trace.cache_status().unwrap(), "devoxx; hit; ttl=500");
+        let mut headers = HeaderMap::new();
+        headers.insert("age", HeaderValue::from_static("7"));
+        trace.apply(&mut headers, true);
+        assert_eq!(headers.get("age").unwrap(), "100");
+        assert_eq!(headers.get(TIER_HEADER).unwrap(), "redis");
+    }
+
+    #[test]
+    fn test_stale_hit_has_negative_ttl() {
+        let mut trace = Trace::new();
+        trace.hit = true;
+        trace.detail = Some("stale-while-revalidate");
+        trace.served_from(Tier::Memory, &policy("max-age=60"), SystemTime::now() - Duration::from_secs(90));
+        assert_eq!(trace.cache_status().unwrap(), "devoxx; hit; ttl=-30; detail=stale-while-revalidate");
+    }
+
+    #[test]
+    fn test_miss_reports_forward_and_storage() {
+        let mut trace = Trace::new();
+        trace.forward = Some(Forward::UriMiss);
+        trace.forward_status = Some(StatusCode::OK);
+        trace.tier = Some(Tier::Origin);
+        trace.stored(&policy("max-age=3600"), SystemTime::now());
+        assert_eq!(trace.cache_status().unwrap(), "devoxx; fwd=uri-miss; fwd-status=200; ttl=3600; stored");
+        let mut headers = HeaderMap::new();
+        trace.apply(&mut headers, false);
+        assert!(headers.get("age").is_none());
+        assert!(headers.get(TIER_HEADER).is_none());
+    }
+
+    #[test]
+    fn test_server_timing_sums_each_tier() {
+        let mut trace = Trace::new();
+        assert!(trace.server_timing().is_none());
+        assert!(trace.cache_status().is_none());
+        let started = Instant::now();
+        trace.record(Tier::Memory, started);
+        trace.record(Tier::Origin, started);
+        trace.record(Tier::Memory, started);
+        let timing = trace.server_timing().unwrap();
+        let metrics: Vec<&str> = timing.split(", ").map(|metric| metric.split(';').next().unwrap()).collect();
+        assert_eq!(metrics, vec!["memory", "origin"]);
+        assert!(timing.contains("memory;dur="));
+    }
+}
@@ -0,0 +1,29 @@
+use std::time::{Duration, SystemTime};
+
+use axum::http::{HeaderMap, HeaderValue};
+
+use crate::cache::{cache_control::CacheControl, policy_util::CachePolicy};
+
+/// builds a header map, keeping repeated names as separate lines
+pub fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+    let mut map = HeaderMap::new();
+    for (name, value) in pairs {
+        map.append(*name, HeaderValue::from_static(value));
+    }
+    map
+}
+
+/// policy of a response carrying only the given cache-control
+pub fn policy(cache_control: &'static str) -> CachePolicy {
+    CachePolicy::new(headers(&[("cache-control", cache_control)]))
+}
+
+/// directives of a single cache-control value
+pub fn cache_control(value: &'static str) -> CacheControl {
+    CacheControl::from_headers(&headers(&[("cache-control", value)]))
+}
+
+/// a point in time `secs` seconds back
+pub fn ago(secs: u64) -> SystemTime {
+    SystemTime::now() - Duration::from_secs(secs)
+}
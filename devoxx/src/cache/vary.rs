@@ -0,0 +1,68 @@
+use axum::http::{header, HeaderMap};
+
+use super::cache_util::CachedResponse;
+
+/// request header names listed in `Vary`, lowercased and sorted, `None` for `Vary: *`
+pub fn vary_names(response_headers: &HeaderMap) -> Option<Vec<String>> {
+    let mut names = Vec::new();
+    for value in response_headers.get_all(header::VARY) {
+        let value = value.to_str().ok()?;
+        for name in value.split(',') {
+            let name = name.trim().to_ascii_lowercase();
+            if name == "*" {
+                return None;
+            }
+            if !name.is_empty() && !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    names.sort();
+    Some(names)
+}
+
+/// secondary key of a response, the values the request had for every header `Vary` names.
+/// empty without `Vary` and `None` for `Vary: *`, which can never be served from the cache
+pub fn variant_key(response_headers: &HeaderMap, request_headers: &HeaderMap) -> Option<String> {
+    let names = vary_names(response_headers)?;
+    let parts: Vec<String> = names
+        .iter()
+        .map(|name| {
+            let values: Vec<String> = request_headers
+                .get_all(name.as_str())
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split(','))
+                .map(|value| value.split_whitespace().collect::<Vec<_>>().join(" "))
+                .filter(|value| !value.is_empty())
+                .collect();
+            format!("{}={}", name, values.join(","))
+        })
+        .collect();
+    Some(parts.join("\n"))
+}
+
+/// the variant stored for a request, `request_part` holds the headers and cookies `[cache.key]`
+/// adds to every key, see `KeyConfig::request_part`
+pub fn with_request_part(variant: String, request_part: &str) -> String {
+    if request_part.is_empty() {
+        variant
+    } else if variant.is_empty() {
+        request_part.to_string()
+    } else {
+        format!("{}\n{}", request_part, variant)
+    }
+}
+
+/// whether a stored variant was produced for a request equivalent to this one
+pub fn matches(cached: &CachedResponse, request_headers: &HeaderMap, request_part: &str) -> bool {
+    variant_key(&cached.headers, request_headers).map(|variant| with_request_part(variant, request_part)).as_deref() == Some(cached.variant.as_str())
+}
+
+/// picks the newest stored variant that matches the request
+pub fn select<I>(variants: I, request_headers: &HeaderMap, request_part: &str) -> Option<CachedResponse>
+where
+    I: IntoIterator<Item = CachedResponse>,
+{
+    variants.into_iter().filter(|cached| matches(cached, request_headers, request_part)).max_by_key(|cached| cached.cached_at)
+}
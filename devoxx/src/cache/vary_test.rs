@@ -0,0 +1,62 @@
+#[cfg(test)]
+mod vary_test {
+    use std::time::{Duration, SystemTime};
+
+    use axum::{body::Bytes, http::{HeaderMap, Method, StatusCode, Uri}};
+
+    use crate::cache::{buffer::Buffer, cache_util::{CacheKey, CachedResponse}, eviction::MemoryConfig, policy_util::CachePolicy, test_util::headers, vary::{select, variant_key, vary_names}};
+
+    fn variant(response: &HeaderMap, request: &HeaderMap, body: &'static str, age: u64) -> CachedResponse {
+        let variant = variant_key(response, request).unwrap();
+        CachedResponse::new(StatusCode::OK, response.clone(), Bytes::from_static(body.as_bytes()), SystemTime::now() - Duration::from_secs(age)).with_variant(variant)
+    }
+
+    #[test]
+    fn test_vary_names_are_normalized() {
+        let response = headers(&[("vary", "Accept-Language, accept-encoding"), ("vary", "ACCEPT-ENCODING")]);
+        assert_eq!(vary_names(&response).unwrap(), vec!["accept-encoding", "accept-language"]);
+        assert!(vary_names(&headers(&[("vary", "accept, *")])).is_none());
+        assert_eq!(variant_key(&HeaderMap::new(), &headers(&[("accept", "x")])).unwrap(), "");
+    }
+
+    #[test]
+    fn test_variant_key_ignores_whitespace_and_other_headers() {
+        let response = headers(&[("vary", "accept-encoding")]);
+        let a = variant_key(&response, &headers(&[("accept-encoding", "gzip,  br"), ("user-agent", "a")])).unwrap();
+        let b = variant_key(&response, &headers(&[("accept-encoding", "gzip, br"), ("user-agent", "b")])).unwrap();
+        assert_eq!(a, b);
+        assert_ne!(a, variant_key(&response, &headers(&[("accept-encoding", "identity")])).unwrap());
+    }
+
+    #[test]
+    fn test_select_picks_matching_variant() {
+        let response = headers(&[("vary", "accept-language")]);
+        let en = headers(&[("accept-language", "en")]);
+        let fr = headers(&[("accept-language", "fr")]);
+        let stored = vec![variant(&response, &en, "hello", 10), variant(&response, &fr, "bonjour", 5)];
+        assert_eq!(select(stored.clone(), &fr, "").unwrap().body, Bytes::from_static(b"bonjour"));
+        assert_eq!(select(stored.clone(), &en, "").unwrap().body, Bytes::from_static(b"hello"));
+        assert!(select(stored, &headers(&[("accept-language", "de")]), "").is_none());
+    }
+
+    #[test]
+    fn test_vary_star_is_not_cacheable() {
+        let policy = CachePolicy::new(headers(&[("cache-control", "max-age=600"), ("vary", "*")]));
+        assert!(!policy.is_cacheable());
+    }
+
+    #[test]
+    fn test_buffer_keeps_variants_apart() {
+        let buffer = Buffer::new(&MemoryConfig::default());
+        let key = CacheKey::new(Method::GET, Uri::from_static("http://origin/page"));
+        let response = headers(&[("vary", "accept-encoding")]);
+        let gzip = headers(&[("accept-encoding", "gzip")]);
+        let plain = headers(&[("accept-encoding", "identity")]);
+        buffer.insert(key.clone(), variant(&response, &gzip, "zipped", 0));
+        assert!(buffer.contains(&key, &gzip, ""));
+        assert!(!buffer.contains(&key, &plain, ""));
+        buffer.insert(key.clone(), variant(&response, &plain, "plain", 0));
+        assert_eq!(buffer.get(&key, &plain, "").unwrap().body, Bytes::from_static(b"plain"));
+        assert_eq!(buffer.get(&key, &gzip, "").unwrap().body, Bytes::from_static(b"zipped"));
+    }
+}
@@ -0,0 +1,378 @@
+use std::{collections::HashSet, fs, net::SocketAddr, path::Path, time::Duration};
+
+use axum::http::uri::Authority;
+use serde::Deserialize;
+
+use crate::cache::{coalesce::CoalesceConfig, eviction::MemoryConfig, freshness::{Defaults, Heuristic}, key::KeyConfig, overrides::{Override, OverrideAction, OverrideRule}, placement::PlacementConfig, policy::PolicyBackend, reaper::ReaperConfig, status::Tier};
+
+/// config file that is picked up from the working directory when `--config` is not given
+pub const DEFAULT_CONFIG_PATH: &str = "devoxx.toml";
+
+/// environment variable that can point at the config file instead of `--config`
+pub const CONFIG_PATH_ENV: &str = "DEVOXX_CONFIG";
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub proxy: ProxyConfig,
+    pub storage: StorageConfig,
+    pub redis: RedisConfig,
+    pub reload: ReloadConfig,
+    pub cache: CacheConfig,
+    pub vhosts: Vec<VhostConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub listen: String,
+    /// path answered with the cache counters as JSON instead of being proxied, off when not set
+    pub stats_path: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProxyConfig {
+    /// authority (host:port) of the upstream used when no `[[vhosts]]` are configured
+    pub origin: String,
+    /// status returned for a host that matches no vhost, either 421 or 404
+    pub unknown_host_status: u16,
+    /// how long an origin request may take before it counts as failed
+    pub timeout_secs: u64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct VhostConfig {
+    /// incoming host name, `*.example.com` matches any subdomain and `*` matches every host
+    pub host: String,
+    pub origin: String,
+    /// cache namespace of the vhost, defaults to the host pattern
+    pub namespace: Option<String>,
+    #[serde(default)]
+    pub routes: Vec<RouteConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RouteConfig {
+    /// path prefix such as `/api` or `/api/*`, matched on whole segments
+    pub prefix: String,
+    /// upstream for this prefix, defaults to the origin of the vhost
+    pub origin: Option<String>,
+    /// drop the prefix before forwarding, `/api/users` becomes `/users`
+    #[serde(default)]
+    pub strip_prefix: bool,
+    /// replace the prefix with this path before forwarding, `/api/users` becomes `/v1/users`
+    pub rewrite: Option<String>,
+    #[serde(default)]
+    pub cache: RouteCacheConfig,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RouteCacheConfig {
+    /// when false responses for the route are always fetched from the origin and never stored
+    pub enabled: bool,
+    /// tiers the route's responses may be stored in, on top of `[cache.placement]`
+    pub tiers: Option<Vec<Tier>>,
+    /// lifetime of the route's responses whatever the origin said, like a `force` rule of
+    /// `[[cache.overrides]]`. a matching override rule still wins
+    pub ttl_secs: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub db_path: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RedisConfig {
+    pub url: String,
+    pub pool_size: i32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    /// give responses that only have `Last-Modified` a share of their age as lifetime
+    pub heuristic_freshness: bool,
+    pub heuristic_fraction: f64,
+    pub heuristic_max_secs: u64,
+    /// how long an expired entry may stand in for a failing origin when the response has no
+    /// `stale-if-error` of its own, 0 serves stale only when the origin allowed it
+    pub stale_if_error_secs: u64,
+    /// lifetime of 404, 410 and 5xx responses without explicit freshness, 0 turns the negative
+    /// cache off
+    pub negative_ttl_secs: u64,
+    /// `simple` or `http-cache-semantics`
+    pub policy: PolicyBackend,
+    /// add `x-devoxx-tier` naming the tier that served the response
+    pub tier_header: bool,
+    /// which tiers receive a cacheable response
+    pub placement: PlacementConfig,
+    /// rules that force, cap or forbid caching whatever the origin said, the first match wins
+    pub overrides: Vec<OverrideRule>,
+    /// how request uris, headers and cookies become cache keys
+    pub key: KeyConfig,
+    /// byte budget and eviction policy of the memory tier
+    pub memory: MemoryConfig,
+    /// the background sweep of expired entries
+    pub reaper: ReaperConfig,
+    /// concurrent misses on one key share an origin fetch
+    pub coalesce: CoalesceConfig,
+}
+
+/// routes and cache rules are always re-read on SIGHUP, `watch` also polls the config file
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReloadConfig {
+    pub watch: bool,
+    pub interval_secs: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig { listen: "0.0.0.0:3001".to_string(), stats_path: None }
+    }
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        ProxyConfig { origin: "localhost:3000".to_string(), unknown_host_status: 421, timeout_secs: 30 }
+    }
+}
+
+impl Default for RouteCacheConfig {
+    fn default() -> Self {
+        RouteCacheConfig { enabled: true, tiers: None, ttl_secs: None }
+    }
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig { db_path: "cache.db".to_string() }
+    }
+}
+
+impl Default for RedisConfig {
+    fn default() -> Self {
+        RedisConfig { url: "redis://localhost:6379".to_string(), pool_size: 5 }
+    }
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig { heuristic_freshness: true, heuristic_fraction: 0.1, heuristic_max_secs: 86400, stale_if_error_secs: 0, negative_ttl_secs: 10, policy: PolicyBackend::Simple, tier_header: false, placement: PlacementConfig::default(), overrides: Vec::new(), key: KeyConfig::default(), memory: MemoryConfig::default(), reaper: ReaperConfig::default(), coalesce: CoalesceConfig::default() }
+    }
+}
+
+impl Default for ReloadConfig {
+    fn default() -> Self {
+        ReloadConfig { watch: false, interval_secs: 5 }
+    }
+}
+
+impl Config {
+    /// loads the config file (an explicit path must exist, the default one is optional),
+    /// applies the `DEVOXX_*` environment overrides and validates the result
+    pub fn load(path: Option<&str>) -> Result<Config, String> {
+        let mut config = match path {
+            Some(path) => Config::from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => Config::from_file(DEFAULT_CONFIG_PATH)?,
+            None => Config::default(),
+        };
+        config.apply_env(|name| std::env::var(name).ok())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &str) -> Result<Config, String> {
+        let contents = fs::read_to_string(path).map_err(|err| format!("could not read config file {} : {}", path, err))?;
+        Config::from_toml(&contents).map_err(|err| format!("{} : {}", path, err))
+    }
+
+    pub fn from_toml(contents: &str) -> Result<Config, String> {
+        toml::from_str(contents).map_err(|err| format!("invalid config : {}", err))
+    }
+
+    /// overrides file values with environment variables, `lookup` is `std::env::var` outside of tests
+    pub fn apply_env<F>(&mut self, lookup: F) -> Result<(), String>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        if let Some(listen) = lookup("DEVOXX_LISTEN") {
+            self.server.listen = listen;
+        }
+        if let Some(origin) = lookup("DEVOXX_ORIGIN") {
+            self.proxy.origin = origin;
+        }
+        if let Some(status) = lookup("DEVOXX_UNKNOWN_HOST_STATUS") {
+            self.proxy.unknown_host_status = status
+                .parse()
+                .map_err(|_| format!("DEVOXX_UNKNOWN_HOST_STATUS must be a number but found {:?}", status))?;
+        }
+        if let Some(db_path) = lookup("DEVOXX_DB_PATH") {
+            self.storage.db_path = db_path;
+        }
+        if let Some(url) = lookup("DEVOXX_REDIS_URL") {
+            self.redis.url = url;
+        }
+        if let Some(pool_size) = lookup("DEVOXX_REDIS_POOL_SIZE") {
+            self.redis.pool_size = pool_size
+                .parse()
+                .map_err(|_| format!("DEVOXX_REDIS_POOL_SIZE must be a number but found {:?}", pool_size))?;
+        }
+        Ok(())
+    }
+
+    /// checks every setting and reports all problems at once
+    pub fn validate(&self) -> Result<(), String> {
+        let mut errors = Vec::new();
+        if self.server.listen.parse::<SocketAddr>().is_err() {
+            errors.push(format!("server.listen : expected an address like 0.0.0.0:3001 but found {:?}", self.server.listen));
+        }
+        if self.proxy.origin.parse::<Authority>().is_err() {
+            errors.push(format!("proxy.origin : expected host:port but found {:?}", self.proxy.origin));
+        }
+        if ![404, 421].contains(&self.proxy.unknown_host_status) {
+            errors.push(format!("proxy.unknown_host_status : expected 421 or 404 but found {}", self.proxy.unknown_host_status));
+        }
+        let mut hosts = HashSet::new();
+        let mut namespaces = HashSet::new();
+        for (i, vhost) in self.vhosts.iter().enumerate() {
+            let host = vhost.host.to_ascii_lowercase();
+            let wildcard = host.strip_prefix("*.").unwrap_or(&host);
+            if host.is_empty() || (host != "*" && wildcard.contains('*')) {
+                errors.push(format!("vhosts[{}].host : expected a host name, *.domain or * but found {:?}", i, vhost.host));
+            }
+            if !hosts.insert(host) {
+                errors.push(format!("vhosts[{}].host : {:?} is configured more than once", i, vhost.host));
+            }
+            if !namespaces.insert(vhost.namespace()) {
+                errors.push(format!("vhosts[{}].namespace : {:?} is used by another vhost", i, vhost.namespace()));
+            }
+            if vhost.origin.parse::<Authority>().is_err() {
+                errors.push(format!("vhosts[{}].origin : expected host:port but found {:?}", i, vhost.origin));
+            }
+            let mut prefixes = HashSet::new();
+            for (j, route) in vhost.routes.iter().enumerate() {
+                let prefix = route.prefix.trim_end_matches('*');
+                if !prefix.starts_with('/') || prefix.contains('*') {
+                    errors.push(format!("vhosts[{}].routes[{}].prefix : expected a path like /api or /api/* but found {:?}", i, j, route.prefix));
+                }
+                if !prefixes.insert(prefix.trim_end_matches('/').to_string()) {
+                    errors.push(format!("vhosts[{}].routes[{}].prefix : {:?} is configured more than once", i, j, route.prefix));
+                }
+                if let Some(origin) = &route.origin {
+                    if origin.parse::<Authority>().is_err() {
+                        errors.push(format!("vhosts[{}].routes[{}].origin : expected host:port but found {:?}", i, j, origin));
+                    }
+                }
+                if let Some(rewrite) = &route.rewrite {
+                    if !rewrite.starts_with('/') {
+                        errors.push(format!("vhosts[{}].routes[{}].rewrite : expected a path starting with / but found {:?}", i, j, rewrite));
+                    }
+                    if route.strip_prefix {
+                        errors.push(format!("vhosts[{}].routes[{}] : strip_prefix and rewrite can not be combined", i, j));
+                    }
+                }
+                if route.cache.tiers.as_ref().is_some_and(|tiers| tiers.contains(&Tier::Origin)) {
+                    errors.push(format!("vhosts[{}].routes[{}].cache.tiers : expected memory, redis or sqlite", i, j));
+                }
+                if route.cache.ttl_secs == Some(0) {
+                    errors.push(format!("vhosts[{}].routes[{}].cache.ttl_secs : must be at least 1, set enabled = false to not cache the route", i, j));
+                }
+            }
+        }
+        if self.storage.db_path.trim().is_empty() {
+            errors.push("storage.db_path : must not be empty".to_string());
+        }
+        if !(self.redis.url.starts_with("redis://") || self.redis.url.starts_with("rediss://")) {
+            errors.push(format!("redis.url : expected a redis:// url but found {:?}", self.redis.url));
+        }
+        if self.proxy.timeout_secs == 0 {
+            errors.push("proxy.timeout_secs : must be at least 1".to_string());
+        }
+        if self.reload.watch && self.reload.interval_secs == 0 {
+            errors.push("reload.interval_secs : must be at least 1 when watch is enabled".to_string());
+        }
+        if !(self.cache.heuristic_fraction > 0.0 && self.cache.heuristic_fraction <= 1.0) {
+            errors.push(format!("cache.heuristic_fraction : must be in (0, 1] but found {}", self.cache.heuristic_fraction));
+        }
+        self.cache.placement.validate(&mut errors);
+        self.cache.key.validate(&mut errors);
+        self.cache.memory.validate(&mut errors);
+        self.cache.reaper.validate(&mut errors);
+        self.cache.coalesce.validate(&mut errors);
+        if matches!(&self.server.stats_path, Some(path) if !path.starts_with('/')) {
+            errors.push(format!("server.stats_path : expected a path like /__devoxx/stats but found {:?}", self.server.stats_path));
+        }
+        for (i, rule) in self.cache.overrides.iter().enumerate() {
+            rule.validate(i, &mut errors);
+        }
+        if self.redis.pool_size < 1 {
+            errors.push(format!("redis.pool_size : must be at least 1 but found {}", self.redis.pool_size));
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+
+    /// the configured vhosts, or a single catch-all vhost for `proxy.origin`
+    pub fn effective_vhosts(&self) -> Vec<VhostConfig> {
+        if self.vhosts.is_empty() {
+            vec![VhostConfig { host: "*".to_string(), origin: self.proxy.origin.clone(), namespace: None, routes: Vec::new() }]
+        } else {
+            self.vhosts.clone()
+        }
+    }
+
+    pub fn listen_addr(&self) -> SocketAddr {
+        self.server.listen.parse().expect("listen address is validated on load")
+    }
+}
+
+impl ProxyConfig {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+}
+
+impl VhostConfig {
+    pub fn namespace(&self) -> String {
+        self.namespace.clone().unwrap_or_else(|| self.host.to_ascii_lowercase())
+    }
+}
+
+impl RouteCacheConfig {
+    /// the forced lifetime as an override, recorded with the entries like a rule's
+    pub fn ttl_override(&self) -> Option<Override> {
+        self.ttl_secs.map(|ttl_secs| Override { rule: "route cache.ttl_secs".to_string(), action: OverrideAction::Force, ttl_secs: Some(ttl_secs) })
+    }
+}
+
+impl CacheConfig {
+    pub fn heuristic(&self) -> Option<Heuristic> {
+        self.heuristic_freshness
+            .then(|| Heuristic { fraction: self.heuristic_fraction, max: Duration::from_secs(self.heuristic_max_secs) })
+    }
+
+    pub fn negative_ttl(&self) -> Option<Duration> {
+        (self.negative_ttl_secs > 0).then(|| Duration::from_secs(self.negative_ttl_secs))
+    }
+
+    /// what the policies fall back on for responses without explicit freshness
+    pub fn defaults(&self) -> Defaults {
+        Defaults { heuristic: self.heuristic(), negative_ttl: self.negative_ttl() }
+    }
+
+    pub fn stale_if_error(&self) -> Option<u64> {
+        (self.stale_if_error_secs > 0).then_some(self.stale_if_error_secs)
+    }
+}
@@ -0,0 +1,85 @@
+#[cfg(test)]
+mod config_test {
+    use std::collections::HashMap;
+
+    use crate::cache::overrides::OverrideAction;
+    use crate::config::config::{Config, RouteCacheConfig};
+
+    #[test]
+    fn test_defaults_are_valid() {
+        let config = Config::default();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.listen_addr().port(), 3001);
+        assert_eq!(config.proxy.origin, "localhost:3000");
+    }
+
+    #[test]
+    fn test_partial_file_keeps_defaults() {
+        let config = Config::from_toml("[redis]\nurl = \"redis://cache:6379\"\n").unwrap();
+        assert_eq!(config.redis.url, "redis://cache:6379");
+        assert_eq!(config.redis.pool_size, 5);
+        assert_eq!(config.server.listen, "0.0.0.0:3001");
+    }
+
+    #[test]
+    fn test_unknown_keys_are_rejected() {
+        let result = Config::from_toml("[server]\nport = 3001\n");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_env_overrides_file() {
+        let mut config = Config::from_toml("[proxy]\norigin = \"localhost:3000\"\n").unwrap();
+        let env: HashMap<&str, &str> = HashMap::from([("DEVOXX_ORIGIN", "origin.internal:8080"), ("DEVOXX_REDIS_POOL_SIZE", "12")]);
+        config.apply_env(|name| env.get(name).map(|v| v.to_string())).unwrap();
+        assert_eq!(config.proxy.origin, "origin.internal:8080");
+        assert_eq!(config.redis.pool_size, 12);
+    }
+
+    #[test]
+    fn test_invalid_env_number() {
+        let mut config = Config::default();
+        let result = config.apply_env(|name| (name == "DEVOXX_REDIS_POOL_SIZE").then(|| "many".to_string()));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_validate_reports_every_error() {
+        let config = Config::from_toml("[server]\nlisten = \"nope\"\n[redis]\nurl = \"http://x\"\npool_size = 0\n").unwrap();
+        let err = config.validate().unwrap_err();
+        assert!(err.contains("server.listen"));
+        assert!(err.contains("redis.url"));
+        assert!(err.contains("redis.pool_size"));
+    }
+
+    #[test]
+    fn test_validate_vhosts() {
+        let config = Config::from_toml(
+            "[proxy]\nunknown_host_status = 500\n[[vhosts]]\nhost = \"a.b\"\norigin = \"x:1\"\n[[vhosts]]\nhost = \"A.B\"\norigin = \"bad origin\"\n[[vhosts]]\nhost = \"a.*.b\"\norigin = \"x:1\"\n",
+        )
+        .unwrap();
+        let err = config.validate().unwrap_err();
+        assert!(err.contains("proxy.unknown_host_status"));
+        assert!(err.contains("vhosts[1].host"));
+        assert!(err.contains("vhosts[1].origin"));
+        assert!(err.contains("vhosts[2].host"));
+    }
+
+    #[test]
+    fn test_route_cache_ttl() {
+        let routes = "[[vhosts]]\nhost = \"a.b\"\norigin = \"x:1\"\n[[vhosts.routes]]\nprefix = \"/api\"\ncache = { ttl_secs = 60 }\n[[vhosts.routes]]\nprefix = \"/v2\"\ncache = { ttl_secs = 0 }\n";
+        let config = Config::from_toml(routes).unwrap();
+        assert!(config.validate().unwrap_err().contains("vhosts[0].routes[1].cache.ttl_secs"));
+        let forced = config.vhosts[0].routes[0].cache.ttl_override().unwrap();
+        assert_eq!((forced.action, forced.ttl_secs), (OverrideAction::Force, Some(60)));
+        assert!(RouteCacheConfig::default().ttl_override().is_none());
+    }
+
+    #[test]
+    fn test_stale_if_error_default() {
+        assert_eq!(Config::default().cache.stale_if_error(), None);
+        let config = Config::from_toml("[proxy]\ntimeout_secs = 0\n[cache]\nstale_if_error_secs = 300\n").unwrap();
+        assert_eq!(config.cache.stale_if_error(), Some(300));
+        assert!(config.validate().unwrap_err().contains("proxy.timeout_secs"));
+    }
+}
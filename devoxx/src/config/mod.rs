@@ -0,0 +1,4 @@
+pub mod config;
+pub mod reload;
+mod config_test;
+mod reload_test;
@@ -0,0 +1,134 @@
+use std::{
+    fs,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use crate::{cache::overrides::Overrides, routing::vhost::VirtualHosts};
+
+use super::config::{Config, DEFAULT_CONFIG_PATH};
+
+/// everything a request needs from the config, swapped as one unit on reload
+#[derive(Debug)]
+pub struct ProxySettings {
+    pub config: Config,
+    pub vhosts: VirtualHosts,
+    pub overrides: Overrides,
+}
+
+/// handle to the live settings, a request takes one snapshot and keeps it until it is done
+#[derive(Debug, Clone)]
+pub struct SharedSettings {
+    inner: Arc<RwLock<Arc<ProxySettings>>>,
+    path: Option<String>,
+}
+
+impl ProxySettings {
+    pub fn new(config: Config) -> Self {
+        let vhosts = VirtualHosts::from_config(&config);
+        let overrides = Overrides::from_config(&config.cache.overrides);
+        ProxySettings { config, vhosts, overrides }
+    }
+}
+
+impl SharedSettings {
+    pub fn new(config: Config, path: Option<String>) -> Self {
+        SharedSettings { inner: Arc::new(RwLock::new(Arc::new(ProxySettings::new(config)))), path }
+    }
+
+    pub fn current(&self) -> Arc<ProxySettings> {
+        self.inner.read().unwrap().clone()
+    }
+
+    /// swaps in a new config, settings that need a restart are kept and reported back
+    pub fn replace(&self, mut config: Config) -> Vec<String> {
+        let mut guard = self.inner.write().unwrap();
+        let current = guard.clone();
+        let mut ignored = Vec::new();
+        if config.server.listen != current.config.server.listen {
+            ignored.push("server.listen");
+            config.server = current.config.server.clone();
+        }
+        if config.storage.db_path != current.config.storage.db_path {
+            ignored.push("storage.db_path");
+            config.storage = current.config.storage.clone();
+        }
+        if config.redis.url != current.config.redis.url || config.redis.pool_size != current.config.redis.pool_size {
+            ignored.push("redis");
+            config.redis = current.config.redis.clone();
+        }
+        // the memory tier and the file watcher are built once at startup
+        if config.cache.memory != current.config.cache.memory {
+            ignored.push("cache.memory");
+            config.cache.memory = current.config.cache.memory.clone();
+        }
+        if config.reload.watch != current.config.reload.watch || config.reload.interval_secs != current.config.reload.interval_secs {
+            ignored.push("reload");
+            config.reload = current.config.reload.clone();
+        }
+        *guard = Arc::new(ProxySettings::new(config));
+        ignored.into_iter().map(|name| format!("{} changed but needs a restart, keeping the old value", name)).collect()
+    }
+
+    /// re-reads the config file and environment, a broken file leaves the running settings untouched
+    pub fn reload(&self) -> Result<(), String> {
+        let config = Config::load(self.path.as_deref())?;
+        for warning in self.replace(config) {
+            println!("config reload : {}", warning);
+        }
+        Ok(())
+    }
+
+    /// reloads on SIGHUP and, when `reload.watch` is set, whenever the config file changes
+    pub fn spawn_reloader(&self) {
+        #[cfg(unix)]
+        {
+            let settings = self.clone();
+            tokio::spawn(async move {
+                use tokio::signal::unix::{signal, SignalKind};
+                let mut hangup = match signal(SignalKind::hangup()) {
+                    Ok(hangup) => hangup,
+                    Err(err) => {
+                        println!("could not listen for SIGHUP : {}", err);
+                        return;
+                    }
+                };
+                while hangup.recv().await.is_some() {
+                    println!("SIGHUP received, reloading config");
+                    settings.log_reload();
+                }
+            });
+        }
+
+        let reload = self.current().config.reload.clone();
+        if !reload.watch {
+            return;
+        }
+        let path = self.path.clone().unwrap_or_else(|| DEFAULT_CONFIG_PATH.to_string());
+        let settings = self.clone();
+        tokio::spawn(async move {
+            let mut last_modified = modified_at(&path);
+            let mut interval = tokio::time::interval(Duration::from_secs(reload.interval_secs));
+            loop {
+                interval.tick().await;
+                let modified = modified_at(&path);
+                if modified != last_modified {
+                    last_modified = modified;
+                    println!("{} changed, reloading config", path);
+                    settings.log_reload();
+                }
+            }
+        });
+    }
+
+    fn log_reload(&self) {
+        match self.reload() {
+            Ok(()) => println!("config reloaded"),
+            Err(err) => println!("config reload failed, keeping the running config\n{}", err),
+        }
+    }
+}
+
+fn modified_at(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
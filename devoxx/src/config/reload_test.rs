@@ -0,0 +1,53 @@
+#[cfg(test)]
+mod reload_test {
+    use std::fs;
+
+    use crate::config::{config::Config, reload::SharedSettings};
+
+    const FIRST: &str = "[[vhosts]]\nhost = \"*\"\norigin = \"first:80\"\n";
+    const SECOND: &str = "[[vhosts]]\nhost = \"*\"\norigin = \"second:80\"\n";
+
+    #[test]
+    fn test_snapshot_survives_replace() {
+        let settings = SharedSettings::new(Config::from_toml(FIRST).unwrap(), None);
+        let in_flight = settings.current();
+        let warnings = settings.replace(Config::from_toml(SECOND).unwrap());
+        assert!(warnings.is_empty());
+        assert_eq!(in_flight.vhosts.resolve("a").unwrap().origin, "first:80");
+        assert_eq!(settings.current().vhosts.resolve("a").unwrap().origin, "second:80");
+    }
+
+    #[test]
+    fn test_restart_only_settings_are_kept() {
+        let settings = SharedSettings::new(Config::from_toml(FIRST).unwrap(), None);
+        let changed = Config::from_toml("[server]\nlisten = \"127.0.0.1:9999\"\n[redis]\npool_size = 9\n").unwrap();
+        let warnings = settings.replace(changed);
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(settings.current().config.server.listen, "0.0.0.0:3001");
+        assert_eq!(settings.current().config.redis.pool_size, 5);
+
+        let changed = Config::from_toml("[cache.memory]\nshards = 4\n[reload]\nwatch = true\n").unwrap();
+        let warnings = settings.replace(changed);
+        assert!(warnings.iter().any(|warning| warning.starts_with("cache.memory ")));
+        assert!(warnings.iter().any(|warning| warning.starts_with("reload ")));
+        assert_eq!(settings.current().config.cache.memory.shards, 16);
+        assert!(!settings.current().config.reload.watch);
+    }
+
+    #[test]
+    fn test_reload_from_file() {
+        let path = std::env::temp_dir().join(format!("devoxx-reload-{}.toml", std::process::id()));
+        let path_str = path.to_str().unwrap().to_string();
+        fs::write(&path, FIRST).unwrap();
+        let settings = SharedSettings::new(Config::from_file(&path_str).unwrap(), Some(path_str.clone()));
+
+        fs::write(&path, "[[vhosts]]\nhost = \"*\"\norigin = \"not an origin\"\n").unwrap();
+        assert!(settings.reload().is_err());
+        assert_eq!(settings.current().vhosts.resolve("a").unwrap().origin, "first:80");
+
+        fs::write(&path, SECOND).unwrap();
+        settings.reload().unwrap();
+        assert_eq!(settings.current().vhosts.resolve("a").unwrap().origin, "second:80");
+        fs::remove_file(&path).unwrap();
+    }
+}
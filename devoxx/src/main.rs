@@ -1,41 +1,82 @@
 mod cache;
+mod config;
+mod routing;
 mod storage;
 
 use core::panic;
 use std::{borrow::Borrow, clone, collections::HashMap, env::vars, error::Error, fs::OpenOptions, hash::Hash, io::Read, net::SocketAddr, sync::{Arc, Mutex}, thread, time};
-use std::time::{SystemTime, Duration};
-use axum::{body::{self, Body, HttpBody,  Bytes}, extract::Host, http::{method, uri::{self, PathAndQuery}, HeaderMap, Request, Response, StatusCode, Uri}, response::IntoResponse, routing::*, RequestExt, Router};
+use std::time::{SystemTime, Duration, Instant};
+use axum::{body::{self, Body, HttpBody,  Bytes}, extract::Host, http::{header, method, uri::{self, PathAndQuery}, HeaderMap, Request, Response, StatusCode, Uri}, response::IntoResponse, routing::*, RequestExt, Router};
 use miette::IntoDiagnostic;
 use axum::extract::State;
 use reqwest::Method;
 use lazy_static::lazy_static;
-use cache::{buffer::Buffer, cache::{cacheableBody, RemoteCacheStore}, policy_util::CachePolicy};
+use cache::{buffer::Buffer, cache::{cacheableBody, RemoteCacheStore}, refresh::Refreshing, invalidate::{invalidates, invalidation_targets}, request::RequestDirectives, status::{Forward, Tier, Trace}, revalidate::{conditional_headers, merge_not_modified, stale_on_error}, vary};
 use storage::{serializer::{cached_response_to_value, cachekey_to_key}, store::DbStore};
-use cache::cache_util::{get_from_cache, insert_into_cache, CacheKey, CachedResponse, is_cached};
+use cache::cache_util::{CacheKey, CachedResponse};
+use cache::{coalesce::{shared_variant, Flight, FlightGuard, Inflight, Lease, Outcome, Wait}, reaper::{Reaper, Tiers}, range::apply_range, key::KeyConfig, overrides::{self, Override}, placement::Placement, policy::Policy};
+use config::{config::{CacheConfig, Config, RouteCacheConfig, CONFIG_PATH_ENV}, reload::{ProxySettings, SharedSettings}};
 
 
 #[derive(Debug, Clone)]
 struct AppState {
     pub store : DbStore,
     pub cacheStore : RemoteCacheStore,
-    pub memMap : Buffer
+    pub memMap : Buffer,
+    pub settings : SharedSettings,
+    pub refreshing : Refreshing,
+    pub reaper : Reaper,
+    pub inflight : Inflight
 }
 
 
-const PROXY_ORGIN_URI : &'static str = "localhost:3000";
-const PROXY_FROM_DOMAIN : &'static str = "client.hello";
-const DEFAULT_PATH : &'static str = "D:/rust-project/devoxy/devoxx/cache.db";
+/// the settings snapshot a request runs with, the cache rules of the route it matched and the
+/// incoming host and path the override rules are matched on
+#[derive(Debug, Clone)]
+struct RequestScope {
+    pub settings : Arc<ProxySettings>,
+    pub route : RouteCacheConfig,
+    pub host : String,
+    pub path : String
+}
+
+
+struct Args { 
+    config_path : Option<String>,
+    check_config : bool
+}
+
+fn parse_args() -> Result<Args, String> { 
+    let mut args = Args { config_path: std::env::var(CONFIG_PATH_ENV).ok(), check_config: false };
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() { 
+        match arg.as_str() { 
+            "--config" | "-c" => {
+                args.config_path = Some(iter.next().ok_or("--config expects a path")?);
+            }
+            "--check-config" => args.check_config = true,
+            other => return Err(format!("unknown argument {}\nusage: devoxx [--config <path>] [--check-config]", other)),
+        }
+    }
+    Ok(args)
+}
+
 //let memory_map = Buffer::new();
 #[tokio::main]
-async fn main() -> Result<(), &'static str> {
-    let vars: Vec<_> = std::env::args().collect();
-    let file_path = { 
-        if let Some(path) = vars.get(1) { 
-            path
-        } else { 
-            DEFAULT_PATH
+async fn main() -> Result<(), String> {
+    let (args, config) = match parse_args().and_then(|args| Config::load(args.config_path.as_deref()).map(|config| (args, config))) { 
+        Ok(loaded) => loaded,
+        Err(err) => { 
+            eprintln!("invalid configuration\n{}", err);
+            std::process::exit(1);
         }
     };
+    if args.check_config { 
+        println!("configuration is valid");
+        println!("{:#?}", config);
+        return Ok(());
+    }
+    let file_path = config.storage.db_path.as_str();
     println!("file path is {}", file_path);
     let db_url = { 
         let file = OpenOptions::new().read(true).write(true).create(true).open(file_path);
@@ -53,24 +94,35 @@ async fn main() -> Result<(), &'static str> {
     };
 
 
-    let remote_cache_store = RemoteCacheStore::new("redis://localhost:6379".to_string(), 5);
-    let memMap = Buffer::new();
-    let mut app_state = AppState { store : DbStore::new(db_url).await.unwrap(), cacheStore: remote_cache_store, memMap};
+    let remote_cache_store = RemoteCacheStore::new(config.redis.url.clone(), config.redis.pool_size);
+    let memMap = Buffer::new(&config.cache.memory);
+    let addr = config.listen_addr();
+    let mut app_state = AppState { store : DbStore::new(db_url).await?, cacheStore: remote_cache_store, memMap, settings: SharedSettings::new(config, args.config_path), refreshing: Refreshing::new(), reaper: Reaper::new(), inflight: Inflight::new()};
+    app_state.settings.spawn_reloader();
+    let tiers = Tiers { memory: app_state.memMap.clone(), redis: app_state.cacheStore.clone(), sqlite: app_state.store.clone() };
+    let reaper = app_state.reaper.spawn(tiers, app_state.settings.clone());
     let cloned_state = app_state.clone();
     let app = Router::new().fallback(|request: Request<Body>| async {
-        let response = proxy_handler(request, cloned_state)
-        .await
-        .map_err(|_| "error").unwrap();
-        response
+        match proxy_handler(request, cloned_state).await { 
+            Ok(response) => response,
+            Err(err) => { 
+                // nothing cached could stand in for the origin
+                println!("request failed : {}", err);
+                let mut response = Response::new(Body::from(err));
+                *response.status_mut() = StatusCode::BAD_GATEWAY;
+                response
+            }
+        }
     });
 
 
     
     
-    let addr = SocketAddr::from(([0, 0, 0, 0], 3001));
     println!("server listening on {}",addr);
 
-    axum::Server::bind(&addr).serve(app.into_make_service()).await.into_diagnostic().map_err(|_| "error".to_string());
+    axum::Server::bind(&addr).serve(app.into_make_service()).with_graceful_shutdown(shutdown_signal()).await.into_diagnostic().map_err(|_| "error".to_string());
+    reaper.shutdown().await;
+    println!("server stopped");
     
 
     
@@ -82,90 +134,557 @@ async fn proxy_handler( mut request: Request<Body>, mut state:  AppState) -> Res
     let method : Method = request.extract_parts().await.unwrap(); 
     let host: Host = request.extract_parts().await.unwrap();
     let req_headers: HeaderMap = request.extract_parts().await.unwrap();
+    // only forwarded by the paths that go straight to the origin, cached lookups never carry one
+    let req_body = read_body(request.into_body()).await?;
     
-    let split : Vec<_>= host.0.split(':').collect(); 
-    let host_name = split[0];
-    //println!("host :{}", host_name);
-    // if host_name != PROXY_FROM_DOMAIN {
-    //     return Err(format!("expected host {} but found {:#?}", PROXY_FROM_DOMAIN.to_string(), host));
-    // }
+    // one snapshot per request, a reload in the middle of it does not change its routing
+    let settings = state.settings.current();
+    if method == Method::GET && settings.config.server.stats_path.as_deref() == Some(uri.path()) { 
+        return stats_response(&state).await;
+    }
+    let vhost = match settings.vhosts.resolve(&host.0) { 
+        Some(vhost) => vhost,
+        None => { 
+            println!("no vhost for host {}", host.0);
+            let status = settings.vhosts.unknown_host_status;
+            return get_response(status, HeaderMap::new(), Bytes::from(format!("unknown host {}", host.0))).await;
+        }
+    };
     //let path = uri.path_and_query().cloned().map(|pq| pq.path()).unwrap_or("/");
     let p_and_q = uri.path_and_query().cloned().unwrap_or_else(|| PathAndQuery::from_static("/"));
+    let target = vhost.route(&p_and_q);
     let url  = uri::Builder::new().scheme("http")
-        .authority(PROXY_ORGIN_URI)
-        .path_and_query(p_and_q.clone())
+        .authority(target.origin.as_str())
+        .path_and_query(target.path_and_query)
         .build()
         .map_err(|_| "could not build url")?;
-    let axum_response = get_cached_response(method, url, req_headers, state).await.map_err(|_| "failed to get cached response")?;
-    Ok(axum_response)
-}
-
-
-
-async fn get_cached_response( method : Method, url: Uri, req_headers : HeaderMap, mut state : AppState) -> Result<Response<Body>, String> {
-    // todo 1.check the cache, if the response is in the cache return here
-        if state.memMap.is_cached(method.clone(), url.clone()) {
-            println!("found");
-            let mut cached_content = state.store.find_page_and_content(CacheKey::new(method.clone(), url.clone())).await;
-            let response = get_response(cached_content.status, cached_content.headers, cached_content.body).await.unwrap();
-            Ok(response)
-            // let mut cached_response = get_from_cache(method.clone(), url.clone());
-            // let mut cached = Arc::make_mut(&mut cached_response);
-            // let ( status, headers, body, cached_at) = cached.get_parts();
-            // let response = get_response(status, headers.clone(), body)
-            // .await.map_err(|_| "failed")?;
-            // Ok(response)
-        } else {
-            let cache_key = CacheKey::new(method.clone(), url.clone());
-            let key = cachekey_to_key(cache_key.clone());
-            let res = state.cacheStore.get(key.clone());
-            if let Ok(cachedResponse) = res { 
-                let status = cachedResponse.status;
-                let headers = cachedResponse.headers;
-                let body = cachedResponse.body;
-                state.memMap.insert_into_cache(method.clone(), url.clone(), status.clone(), headers.clone(), body.clone()).await;
-                let response = get_response(status, headers, body).await.map_err(|err| err.to_string()).unwrap();
-                return Ok(response);
+    let mut trace = Trace::new();
+    let mut response = if !target.cache.enabled { 
+        trace.forward = Some(Forward::Bypass);
+        trace.tier = Some(Tier::Origin);
+        let started = Instant::now();
+        let (status, headers, body) = fetch_from_origin(method.clone(), url, req_headers, req_body, settings.config.proxy.timeout()).await?;
+        trace.record(Tier::Origin, started);
+        get_response(status, headers, body).await?
+    } else if !matches!(method, Method::GET | Method::HEAD) { 
+        // other methods are never stored, unsafe ones purge what they may have changed
+        trace.forward = Some(Forward::Method);
+        trace.tier = Some(Tier::Origin);
+        let started = Instant::now();
+        let (status, headers, body) = fetch_from_origin(method.clone(), url.clone(), req_headers, req_body, settings.config.proxy.timeout()).await?;
+        trace.record(Tier::Origin, started);
+        if invalidates(&method, status) { 
+            invalidate(&vhost.namespace, invalidation_targets(&url, &headers), &settings.config.cache.key, &mut state).await;
+        }
+        get_response(status, headers, body).await?
+    } else { 
+        // HEAD is answered from the stored GET
+        let cache_key = CacheKey::normalized(&vhost.namespace, Method::GET, &url, &settings.config.cache.key);
+        let scope = RequestScope { settings: settings.clone(), route: target.cache, host: host.0.clone(), path: uri.path().to_string() };
+        // ranges are cut from the full body, the tiers and the origin only ever see full requests
+        let mut lookup_headers = req_headers.clone();
+        lookup_headers.remove(header::RANGE);
+        lookup_headers.remove(header::IF_RANGE);
+        let response = get_cached_response(cache_key, method.clone(), url, lookup_headers, scope, state, &mut trace).await?;
+        if method == Method::GET && response.status() == StatusCode::OK { 
+            serve_range(&req_headers, response).await?
+        } else { 
+            response
+        }
+    };
+    if method == Method::HEAD { 
+        *response.body_mut() = Body::empty();
+    }
+    trace.apply(response.headers_mut(), settings.config.cache.tier_header);
+    Ok(response)
+}
+
+
+
+async fn get_cached_response( cache_key : CacheKey, method : Method, url : Uri, req_headers : HeaderMap, scope : RequestScope, mut state : AppState, trace : &mut Trace) -> Result<Response<Body>, String> {
+    let cache_config = &scope.settings.config.cache;
+    let directives = RequestDirectives::from_headers(&req_headers);
+    let mut stale = match lookup(&cache_key, &req_headers, &directives, &scope, &mut state, trace).await { 
+        Lookup::Served(response) => return response,
+        Lookup::Miss(stale) => stale,
+    };
+
+    if let Some((tier, cached)) = stale.as_ref().filter(|(_, cached)| !directives.forces_revalidation() && serves_while_revalidating(cached, cache_config)) { 
+        println!("serving stale entry while revalidating");
+        trace.hit = true;
+        trace.detail = Some("stale-while-revalidate");
+        trace.served_from(*tier, cache_config.policy.for_entry(cached, cache_config.defaults()).as_ref(), cached.cached_at);
+        spawn_refresh(cache_key.clone(), url.clone(), req_headers.clone(), (*tier, cached.clone()), scope.clone(), state.clone());
+        return get_response(cached.status, cached.headers.clone(), cached.body.clone()).await;
+    }
+    if directives.only_if_cached() { 
+        println!("only-if-cached miss");
+        trace.detail = Some("only-if-cached");
+        return get_response(StatusCode::GATEWAY_TIMEOUT, HeaderMap::new(), Bytes::from_static(b"not cached")).await;
+    }
+    // concurrent misses on the key share one origin fetch, the others get the leader's response
+    // or error and only fetch themselves when it answers another variant or took too long
+    let coalesce = &cache_config.coalesce;
+    let leading = if method == Method::GET && coalesce.enabled { 
+        match state.inflight.join(&cache_key) { 
+            Flight::Leader(guard) => Some(guard),
+            Flight::Waiter(waiter) => { 
+                println!("waiting for a running fetch");
+                let look_again = match waiter.wait(coalesce.wait_timeout()).await { 
+                    Wait::Done(outcome) => match outcome.for_request(&req_headers, &cache_config.key) { 
+                        Some(shared) => { 
+                            trace.detail = Some("collapsed");
+                            trace.forward = Some(Forward::UriMiss);
+                            trace.tier = Some(Tier::Origin);
+                            let (status, headers, body) = shared?;
+                            trace.forward_status = Some(status);
+                            return get_response(status, headers, body).await;
+                        }
+                        None => { 
+                            println!("running fetch answered another variant");
+                            true
+                        }
+                    },
+                    Wait::Abandoned => { 
+                        println!("running fetch was abandoned");
+                        true
+                    }
+                    Wait::TimedOut => { 
+                        println!("running fetch is taking too long, fetching");
+                        false
+                    }
+                };
+                if look_again { 
+                    match lookup(&cache_key, &req_headers, &directives, &scope, &mut state, trace).await { 
+                        Lookup::Served(response) => return response,
+                        Lookup::Miss(found) => stale = found.or(stale),
+                    }
+                }
+                None
             }
-            let client = reqwest::Client::new();
-            let (status, headers, bytes) = client.request(method.clone(), url.clone().to_string()).headers(req_headers.clone()).send().await
-                    .map(|r| (r.status(), r.headers().clone(), r.bytes()))
-                    .map_err(|_|"failed")
-                    .unwrap();
-            
-            let body = bytes.await.into_diagnostic().map_err(|_|"failed to parse body");
-            let cachedRespone = CachedResponse::new(status, headers.clone(), body.clone().unwrap(), SystemTime::now());
-            let value  = cached_response_to_value(cachedRespone);
-            println!("key is {:#?} value is {:#?}", key, value);
-            let cacheable = cacheableBody {key, value};
-            println!("cachedable body is {:#?}", cacheable);
-            let result = state.cacheStore.set(cacheable);
-            match result {
-                Ok(str) => println!("added to cache" ),
-                Err(err) => println!("error result : {}", err),
+        }
+    } else { 
+        None
+    };
+    // instances sharing redis also agree on one fetch, the others poll the tiers for its result
+    let _lease = if leading.is_some() && coalesce.distributed && state.cacheStore.is_connected() { 
+        let key = cachekey_to_key(cache_key.clone());
+        match Lease::acquire(&state.cacheStore, key.clone(), coalesce.lease_ttl()) { 
+            Ok(Some(lease)) => Some(lease),
+            Ok(None) => { 
+                println!("another instance is fetching, polling for its result");
+                if let Some(response) = await_remote_fetch(&cache_key, &req_headers, &directives, &scope, &mut state, trace).await { 
+                    return response;
+                }
+                // the holder gave up or died, whoever takes the lease now fetches for the others
+                Lease::acquire(&state.cacheStore, key, coalesce.lease_ttl()).ok().flatten()
             }
-            let policy = CachePolicy::new(headers.clone());
-            if policy.is_cacheable() { 
-                insert_into_cache(method.clone(), url.clone(), status, headers.clone(), body.clone().unwrap());
-                println!("cacheable")
-            } else { 
-                println!("not cacheable")
+            Err(err) => { 
+                println!("could not take the fetch lease : {}", err);
+                None
             }
-            if policy.is_storable_to_disk() { 
-                let key = CacheKey::new(method.clone(), url.clone());
-                let content = CachedResponse::new(status, headers.clone(), body.clone().unwrap(), SystemTime::now());
-                let added = state.store.add(key, content).await;
-                match added {
-                    Ok(_) => println!("added"),
-                    Err(err) => println!("error adding to disk : {}", err),
-                }
-                
+        }
+    } else { 
+        None
+    };
+    trace.forward = Some(match &stale { 
+        None => Forward::UriMiss,
+        Some((_, cached)) if is_stale(cached, cache_config) => Forward::Stale,
+        Some(_) => Forward::Request,
+    });
+    let response = revalidate_or_fetch(&cache_key, &method, &url, &req_headers, stale, &scope, &mut state, trace).await;
+    // what the cache did not keep is not handed over either, the waiters fetch it themselves
+    let kept = trace.stored || trace.tier.is_some_and(|tier| tier != Tier::Origin);
+    match leading { 
+        Some(guard) => hand_over(guard, response, kept, &req_headers, &cache_config.key).await,
+        None => response,
+    }
+}
+
+
+/// hands the leader's response or error to the requests waiting for the same key, a response only
+/// when the cache `kept` it
+async fn hand_over(guard : FlightGuard, response : Result<Response<Body>, String>, kept : bool, req_headers : &HeaderMap, key_config : &KeyConfig) -> Result<Response<Body>, String> { 
+    let response = match response { 
+        Ok(response) => response,
+        Err(err) => { 
+            guard.finish(Outcome::Failed(err.clone()));
+            return Err(err);
+        }
+    };
+    let (parts, body) = response.into_parts();
+    let body = read_body(body).await?;
+    let variant = shared_variant(parts.status, &parts.headers, req_headers, key_config).filter(|_| kept);
+    guard.finish(Outcome::Response { status: parts.status, headers: parts.headers.clone(), body: body.clone(), variant });
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
+
+
+/// polls the tiers while another instance holds the fetch lease of the key, `None` once the
+/// lease is gone or the wait timed out without a usable entry showing up
+async fn await_remote_fetch(cache_key : &CacheKey, req_headers : &HeaderMap, directives : &RequestDirectives, scope : &RequestScope, state : &mut AppState, trace : &mut Trace) -> Option<Result<Response<Body>, String>> { 
+    let coalesce = &scope.settings.config.cache.coalesce;
+    let key = cachekey_to_key(cache_key.clone());
+    let deadline = Instant::now() + coalesce.wait_timeout();
+    while Instant::now() < deadline { 
+        tokio::time::sleep(coalesce.poll_interval()).await;
+        // read before the lookup, a lease released in between means the entry is already written
+        let held = state.cacheStore.lease_held(&key).unwrap_or(false);
+        if let Lookup::Served(response) = lookup(cache_key, req_headers, directives, scope, state, trace).await { 
+            trace.detail = Some("collapsed");
+            return Some(response);
+        }
+        if !held { 
+            println!("fetch lease released without a usable entry");
+            return None;
+        }
+    }
+    println!("remote fetch is taking too long, fetching");
+    None
+}
+
+
+/// what the tiers hold for a request
+enum Lookup { 
+    /// an entry the client and the policy accept
+    Served(Result<Response<Body>, String>),
+    /// the newest stale copy found in any tier, revalidated with the origin instead of refetched
+    Miss(Option<(Tier, CachedResponse)>)
+}
+
+
+/// looks the request up in memory, redis and sqlite in that order
+async fn lookup(cache_key : &CacheKey, req_headers : &HeaderMap, directives : &RequestDirectives, scope : &RequestScope, state : &mut AppState, trace : &mut Trace) -> Lookup { 
+    let cache_config = &scope.settings.config.cache;
+    let request_part = cache_config.key.request_part(req_headers);
+    let mut stale : Option<(Tier, CachedResponse)> = None;
+
+    let started = Instant::now();
+    let cached = state.memMap.get(cache_key, req_headers, &request_part);
+    trace.record(Tier::Memory, started);
+    if let Some(cached) = cached { 
+        if let Some(response) = serve_if_acceptable(Tier::Memory, (*cached).clone(), directives, cache_config, &mut stale, trace).await { 
+            return Lookup::Served(response);
+        }
+    }
+
+    // an unreachable redis is skipped, the other tiers keep serving
+    if state.cacheStore.is_connected() { 
+        let started = Instant::now();
+        let cached = state.cacheStore.get_variants(cachekey_to_key(cache_key.clone())).map(|variants| vary::select(variants, req_headers, &request_part));
+        trace.record(Tier::Redis, started);
+        if let Ok(Some(cached)) = cached { 
+            promote(cache_key, &cached, scope, state).await;
+            if let Some(response) = serve_if_acceptable(Tier::Redis, cached, directives, cache_config, &mut stale, trace).await { 
+                return Lookup::Served(response);
             }
-            let response = get_response(status, headers, body.unwrap())
-                .await.map_err(|_|"error")?;
-            Ok(response)
         }
+    }
+
+    let started = Instant::now();
+    let cached = state.store.find_variants(cache_key.clone()).await.map(|variants| vary::select(variants, req_headers, &request_part));
+    trace.record(Tier::Sqlite, started);
+    if let Ok(Some(cached)) = cached { 
+        promote(cache_key, &cached, scope, state).await;
+        if let Some(response) = serve_if_acceptable(Tier::Sqlite, cached, directives, cache_config, &mut stale, trace).await { 
+            return Lookup::Served(response);
+        }
+    }
+    Lookup::Miss(stale)
+}
+
+
+/// serves an entry found in `tier` when the client and the policy accept it, otherwise keeps it
+/// as the stale copy if it is newer than the one already found
+async fn serve_if_acceptable(tier : Tier, cached : CachedResponse, directives : &RequestDirectives, cache_config : &CacheConfig, stale : &mut Option<(Tier, CachedResponse)>, trace : &mut Trace) -> Option<Result<Response<Body>, String>> { 
+    let policy = cache_config.policy.for_entry(&cached, cache_config.defaults());
+    if directives.accepts(policy.as_ref(), cached.cached_at) { 
+        println!("hit in {}", tier.as_str());
+        trace.hit = true;
+        trace.served_from(tier, policy.as_ref(), cached.cached_at);
+        return Some(get_response(cached.status, cached.headers, cached.body).await);
+    }
+    println!("stale entry in {}", tier.as_str());
+    if stale.as_ref().is_none_or(|(_, s)| s.cached_at < cached.cached_at) { 
+        *stale = Some((tier, cached));
+    }
+    None
+}
+
+
+/// asks the origin for `url` again, conditionally when the stale copy has validators, and updates
+/// the tiers under `cache_key`. when the origin fails the stale copy is served instead if its
+/// stale-if-error window allows it
+async fn revalidate_or_fetch(cache_key : &CacheKey, method : &Method, url : &Uri, req_headers : &HeaderMap, stale : Option<(Tier, CachedResponse)>, scope : &RequestScope, state : &mut AppState, trace : &mut Trace) -> Result<Response<Body>, String> { 
+    let cache_config = &scope.settings.config.cache;
+    let timeout = scope.settings.config.proxy.timeout();
+    let fallback = stale.clone().filter(|(_, cached)| serves_on_error(cached, cache_config));
+    let conditional = stale.as_ref().and_then(|(_, cached)| conditional_headers(req_headers, cached));
+    if conditional.is_some() { 
+        println!("revalidating stale entry");
+    }
+    let started = Instant::now();
+    let fetched = fetch_from_origin(method.clone(), url.clone(), conditional.clone().unwrap_or_else(|| req_headers.clone()), Bytes::new(), timeout).await;
+    trace.record(Tier::Origin, started);
+    let (status, headers, body) = match (fetched, fallback) { 
+        (Err(err), Some((tier, cached))) => { 
+            println!("origin failed, serving stale entry : {}", err);
+            trace.detail = Some("stale-if-error");
+            trace.served_from(tier, cache_config.policy.for_entry(&cached, cache_config.defaults()).as_ref(), cached.cached_at);
+            return get_response(cached.status, stale_on_error(&cached), cached.body).await;
+        }
+        (Ok((status, _, _)), Some((tier, cached))) if status.is_server_error() => { 
+            println!("origin answered {}, serving stale entry", status);
+            trace.forward_status = Some(status);
+            trace.detail = Some("stale-if-error");
+            trace.served_from(tier, cache_config.policy.for_entry(&cached, cache_config.defaults()).as_ref(), cached.cached_at);
+            return get_response(cached.status, stale_on_error(&cached), cached.body).await;
+        }
+        (fetched, _) => fetched?,
+    };
+    trace.forward_status = Some(status);
+    if let (StatusCode::NOT_MODIFIED, Some((tier, cached)), Some(_)) = (status, stale, conditional) { 
+        let now = SystemTime::now();
+        let refreshed = merge_not_modified(&cached, &headers, now);
+        // the policy state and the override have to follow the new headers and response time
+        let ttl_override = ttl_override(scope, cache_key, &refreshed.headers);
+        let policy = cache_config.policy.for_response(cache_key, req_headers, refreshed.status, &refreshed.headers, now, cache_config.defaults());
+        let policy = overrides::apply(policy, ttl_override.as_ref(), refreshed.status, &refreshed.headers);
+        let refreshed = refreshed.with_policy(policy.state()).with_override(ttl_override);
+        trace.served_from(tier, policy.as_ref(), now);
+        let placement = placement(scope, policy.as_ref(), &refreshed);
+        refresh_tiers(cache_key, &refreshed, placement, state).await;
+        return get_response(refreshed.status, refreshed.headers, refreshed.body).await;
+    }
+    trace.tier = Some(Tier::Origin);
+    if *method == Method::HEAD { 
+        // a HEAD response has no body to store for the GET
+        return get_response(status, headers, body).await;
+    }
+    store_response(cache_key, req_headers, (status, headers, body), scope, state, trace).await
+}
+
+
+/// refreshes a stale entry that was already served, the client does not wait for the origin
+fn spawn_refresh(cache_key : CacheKey, url : Uri, req_headers : HeaderMap, stale : (Tier, CachedResponse), scope : RequestScope, mut state : AppState) { 
+    let Some(guard) = state.refreshing.start(&cache_key, &stale.1.variant) else { 
+        println!("refresh already running");
+        return;
+    };
+    tokio::spawn(async move { 
+        let _guard = guard;
+        let method = cache_key.0.clone();
+        match revalidate_or_fetch(&cache_key, &method, &url, &req_headers, Some(stale), &scope, &mut state, &mut Trace::new()).await { 
+            Ok(_) => println!("background refresh done"),
+            Err(err) => println!("background refresh failed : {}", err),
+        }
+    });
+}
+
+
+/// drops the stored GETs of every uri from all tiers after a successful unsafe request
+async fn invalidate(namespace : &str, uris : Vec<Uri>, key_config : &KeyConfig, state : &mut AppState) { 
+    for uri in uris { 
+        let cache_key = CacheKey::normalized(namespace, Method::GET, &uri, key_config);
+        state.memMap.remove(&cache_key);
+        if state.cacheStore.is_connected() { 
+            if let Err(err) = state.cacheStore.remove(cachekey_to_key(cache_key.clone())) { 
+                println!("error invalidating redis entry : {}", err);
+            }
+        }
+        if let Err(err) = state.store.remove(cache_key.clone()).await { 
+            println!("error invalidating disk entry : {}", err);
+        }
+        println!("invalidated {}", cache_key.1);
+    }
+}
+
+
+/// writes a full origin response to the tiers its placement allows and turns it into the client response
+async fn store_response(cache_key : &CacheKey, req_headers : &HeaderMap, fetched : (StatusCode, HeaderMap, Bytes), scope : &RequestScope, state : &mut AppState, trace : &mut Trace) -> Result<Response<Body>, String> { 
+    let (status, headers, body) = fetched;
+    if status == StatusCode::PARTIAL_CONTENT { 
+        // never a complete object, even if the origin sent one without being asked for a range
+        println!("partial response, not cached");
+        return get_response(status, headers, body).await;
+    }
+    if RequestDirectives::from_headers(req_headers).bypasses_storage() { 
+        println!("request no-store, not cached");
+        return get_response(status, headers, body).await;
+    }
+    let Some(variant) = vary::variant_key(&headers, req_headers) else { 
+        println!("vary * response, not cached");
+        return get_response(status, headers, body).await;
+    };
+    let cache_config = &scope.settings.config.cache;
+    let variant = vary::with_request_part(variant, &cache_config.key.request_part(req_headers));
+    let now = SystemTime::now();
+    let ttl_override = ttl_override(scope, cache_key, &headers);
+    if let Some(ttl_override) = &ttl_override { 
+        println!("override {} applies : {:?}", ttl_override.rule, ttl_override.action);
+    }
+    let policy = cache_config.policy.for_response(cache_key, req_headers, status, &headers, now, cache_config.defaults());
+    let policy = overrides::apply(policy, ttl_override.as_ref(), status, &headers);
+    let cached_response = CachedResponse::new(status, headers.clone(), body.clone(), now).with_variant(variant).with_policy(policy.state()).with_override(ttl_override);
+    let placement = placement(scope, policy.as_ref(), &cached_response);
+    if !placement.any() { 
+        println!("not cacheable");
+        return get_response(status, headers, body).await;
+    }
+    trace.stored(policy.as_ref(), now);
+    println!("placing in {:?}", placement);
+    if placement.memory { 
+        state.memMap.insert(cache_key.clone(), cached_response.clone());
+    }
+    if placement.redis && state.cacheStore.is_connected() { 
+        let key = cachekey_to_key(cache_key.clone());
+        let value  = cached_response_to_value(cached_response.clone());
+        let cacheable = cacheableBody {key, value};
+        match state.cacheStore.set(cacheable) {
+            Ok(_) => println!("added to cache" ),
+            Err(err) => println!("error result : {}", err),
+        }
+    }
+    if placement.sqlite { 
+        let added = state.store.add(cache_key.clone(), cached_response).await;
+        match added {
+            Ok(_) => println!("added"),
+            Err(err) => println!("error adding to disk : {}", err),
+        }
+        
+    }
+    let response = get_response(status, headers, body)
+        .await.map_err(|_|"error")?;
+    Ok(response)
+}
+
+
+/// pushes a revalidated entry back into the tiers it is placed in, only the sqlite headers are rewritten
+async fn refresh_tiers(cache_key : &CacheKey, refreshed : &CachedResponse, placement : Placement, state : &mut AppState) { 
+    if placement.memory { 
+        state.memMap.insert(cache_key.clone(), refreshed.clone());
+    }
+    if placement.redis && state.cacheStore.is_connected() { 
+        let cacheable = cacheableBody { key: cachekey_to_key(cache_key.clone()), value: cached_response_to_value(refreshed.clone()) };
+        if let Err(err) = state.cacheStore.set(cacheable) { 
+            println!("error refreshing redis entry : {}", err);
+        }
+    }
+    if placement.sqlite { 
+        match state.store.refresh(cache_key.clone(), refreshed.clone()).await { 
+            Ok(rows) => println!("refreshed {} rows on disk", rows),
+            Err(err) => println!("error refreshing disk entry : {}", err),
+        }
+    }
+}
+
+
+/// the first `[[cache.overrides]]` rule matching the request and the response headers, then the
+/// `ttl_secs` of the route
+fn ttl_override(scope : &RequestScope, cache_key : &CacheKey, headers : &HeaderMap) -> Option<Override> { 
+    scope.settings.overrides.find(&scope.host, &scope.path, cache_key.0.as_str(), headers).or_else(|| scope.route.ttl_override())
+}
+
+
+/// tiers a response belongs in, nothing when its policy does not let a shared cache keep it
+fn placement(scope : &RequestScope, policy : &dyn Policy, cached : &CachedResponse) -> Placement { 
+    if !policy.is_cacheable() { 
+        return Placement::default();
+    }
+    scope.settings.config.cache.placement.place(scope.route.tiers.as_deref(), policy.lifetime(cached.cached_at), cached.body.len(), &cached.headers)
+}
+
+
+/// copies an entry found in a slower tier into memory when memory may hold it, stale entries
+/// included so they can be revalidated from there
+async fn promote(cache_key : &CacheKey, cached : &CachedResponse, scope : &RequestScope, state : &mut AppState) { 
+    let cache_config = &scope.settings.config.cache;
+    let lifetime = cache_config.policy.for_entry(cached, cache_config.defaults()).lifetime(cached.cached_at);
+    if cache_config.placement.place(scope.route.tiers.as_deref(), lifetime, cached.body.len(), &cached.headers).memory { 
+        state.memMap.insert(cache_key.clone(), cached.clone());
+    }
+}
+
+
+fn is_stale(cached : &CachedResponse, cache_config : &CacheConfig) -> bool { 
+    cache_config.policy.for_entry(cached, cache_config.defaults()).is_stale(cached.cached_at)
+}
+
+
+/// a negatively cached 5xx is no better than the error it would stand in for
+fn serves_on_error(cached : &CachedResponse, cache_config : &CacheConfig) -> bool { 
+    !cached.status.is_server_error() && cache_config.policy.for_entry(cached, cache_config.defaults()).can_serve_on_error(cached.cached_at, cache_config.stale_if_error())
+}
+
+
+fn serves_while_revalidating(cached : &CachedResponse, cache_config : &CacheConfig) -> bool { 
+    cache_config.policy.for_entry(cached, cache_config.defaults()).can_serve_while_revalidating(cached.cached_at)
+}
+
+
+async fn fetch_from_origin(method : Method, url : Uri, req_headers : HeaderMap, req_body : Bytes, timeout : Duration) -> Result<(StatusCode, HeaderMap, Bytes), String> { 
+    let client = reqwest::Client::builder().timeout(timeout).build()
+        .map_err(|err| format!("could not build origin client : {}", err))?;
+    let mut request = client.request(method, url.to_string()).headers(req_headers);
+    if !req_body.is_empty() { 
+        request = request.body(req_body);
+    }
+    let response = request.send().await
+        .map_err(|err| format!("origin request failed : {}", err))?;
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = response.bytes().await.map_err(|err| format!("failed to read origin body : {}", err))?;
+    Ok((status, headers, body))
+}
+
+
+/// answers the client's `Range` from a full 200 response, see `cache::range`
+async fn serve_range(req_headers : &HeaderMap, mut response : Response<Body>) -> Result<Response<Body>, String> { 
+    if !req_headers.contains_key(header::RANGE) { 
+        response.headers_mut().insert(header::ACCEPT_RANGES, header::HeaderValue::from_static("bytes"));
+        return Ok(response);
+    }
+    let (parts, body) = response.into_parts();
+    let (status, headers, body) = apply_range(req_headers, parts.status, parts.headers, read_body(body).await?);
+    get_response(status, headers, body).await
+}
+
+
+async fn read_body(mut body : Body) -> Result<Bytes, String> { 
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await { 
+        bytes.extend_from_slice(&chunk.map_err(|err| format!("failed to read body : {}", err))?);
+    }
+    Ok(Bytes::from(bytes))
+}
+
+
+/// resolves on ctrl-c or SIGTERM, in-flight requests are finished before the server returns
+async fn shutdown_signal() { 
+    #[cfg(unix)]
+    { 
+        use tokio::signal::unix::{signal, SignalKind};
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) { 
+            tokio::select! { 
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+            println!("shutting down");
+            return;
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+    println!("shutting down");
+}
+
 
+/// the counters of the cache as JSON, served on `server.stats_path`
+async fn stats_response(state : &AppState) -> Result<Response<Body>, String> { 
+    let stats = serde_json::json!({ "memory": state.memMap.stats(), "reaper": state.reaper.stats() });
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, header::HeaderValue::from_static("application/json"));
+    headers.insert(header::CACHE_CONTROL, header::HeaderValue::from_static("no-store"));
+    get_response(StatusCode::OK, headers, Bytes::from(stats.to_string())).await
 }
 
 
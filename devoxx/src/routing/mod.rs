@@ -0,0 +1,4 @@
+pub mod route;
+pub mod vhost;
+mod route_test;
+mod vhost_test;
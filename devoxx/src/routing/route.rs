@@ -0,0 +1,92 @@
+use axum::http::uri::PathAndQuery;
+
+use crate::config::config::{RouteCacheConfig, RouteConfig};
+
+#[derive(Debug, Clone)]
+pub struct Route {
+    /// normalized prefix without a trailing `/` or `/*`, `/` itself is kept as the empty string
+    pub prefix: String,
+    pub origin: Option<String>,
+    /// what the prefix is replaced with before forwarding, `None` keeps the path untouched
+    pub rewrite: Option<String>,
+    pub cache: RouteCacheConfig,
+}
+
+/// where a request goes after host and path matching
+#[derive(Debug, Clone)]
+pub struct RouteTarget {
+    pub origin: String,
+    pub path_and_query: PathAndQuery,
+    pub cache: RouteCacheConfig,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RouteTable {
+    routes: Vec<Route>,
+}
+
+impl Route {
+    pub fn from_config(route: &RouteConfig) -> Self {
+        let prefix = route.prefix.trim_end_matches('*').trim_end_matches('/').to_string();
+        let rewrite = if route.strip_prefix {
+            Some(String::new())
+        } else {
+            route.rewrite.as_ref().map(|rewrite| rewrite.trim_end_matches('/').to_string())
+        };
+        Route { prefix, origin: route.origin.clone(), rewrite, cache: route.cache.clone() }
+    }
+
+    /// prefixes only match whole path segments, `/api` matches `/api` and `/api/x` but not `/apix`
+    pub fn matches(&self, path: &str) -> bool {
+        match path.strip_prefix(self.prefix.as_str()) {
+            Some(rest) => rest.is_empty() || rest.starts_with('/'),
+            None => false,
+        }
+    }
+
+    fn rewrite_path(&self, path: &str) -> String {
+        match &self.rewrite {
+            Some(replacement) => {
+                let rest = &path[self.prefix.len()..];
+                let rewritten = format!("{}{}", replacement, rest);
+                if rewritten.starts_with('/') {
+                    rewritten
+                } else {
+                    format!("/{}", rewritten)
+                }
+            }
+            None => path.to_string(),
+        }
+    }
+}
+
+impl RouteTable {
+    /// routes keep their config order but longer prefixes are always tried first
+    pub fn new(mut routes: Vec<Route>) -> Self {
+        routes.sort_by_key(|route| std::cmp::Reverse(route.prefix.len()));
+        RouteTable { routes }
+    }
+
+    pub fn from_config(routes: &[RouteConfig]) -> Self {
+        RouteTable::new(routes.iter().map(Route::from_config).collect())
+    }
+
+    pub fn find(&self, path: &str) -> Option<&Route> {
+        self.routes.iter().find(|route| route.matches(path))
+    }
+
+    /// picks the route for the request and builds the upstream path, falling back to `default_origin`
+    pub fn resolve(&self, default_origin: &str, p_and_q: &PathAndQuery) -> RouteTarget {
+        let Some(route) = self.find(p_and_q.path()) else {
+            return RouteTarget { origin: default_origin.to_string(), path_and_query: p_and_q.clone(), cache: RouteCacheConfig::default() };
+        };
+        let path = route.rewrite_path(p_and_q.path());
+        let rewritten = match p_and_q.query() {
+            Some(query) => format!("{}?{}", path, query),
+            None => path,
+        };
+        let path_and_query = rewritten.parse().unwrap_or_else(|_| p_and_q.clone());
+        let origin = route.origin.clone().unwrap_or_else(|| default_origin.to_string());
+        RouteTarget { origin, path_and_query, cache: route.cache.clone() }
+    }
+}
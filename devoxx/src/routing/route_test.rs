@@ -0,0 +1,64 @@
+#[cfg(test)]
+mod route_test {
+    use axum::http::uri::PathAndQuery;
+
+    use crate::{config::config::Config, routing::route::RouteTable};
+
+    fn table() -> RouteTable {
+        let config = Config::from_toml(
+            r#"
+            [[vhosts]]
+            host = "*"
+            origin = "web:80"
+
+            [[vhosts.routes]]
+            prefix = "/api/*"
+            origin = "api:8080"
+            strip_prefix = true
+
+            [[vhosts.routes]]
+            prefix = "/api/v2"
+            origin = "api-v2:8080"
+            rewrite = "/next"
+            cache = { enabled = false }
+
+            [[vhosts.routes]]
+            prefix = "/static"
+            origin = "assets:9000"
+            "#,
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+        RouteTable::from_config(&config.vhosts[0].routes)
+    }
+
+    fn resolve(path: &str) -> (String, String, bool) {
+        let target = table().resolve("web:80", &PathAndQuery::try_from(path).unwrap());
+        (target.origin, target.path_and_query.to_string(), target.cache.enabled)
+    }
+
+    #[test]
+    fn test_prefix_strip() {
+        assert_eq!(resolve("/api/users?id=1"), ("api:8080".to_string(), "/users?id=1".to_string(), true));
+        assert_eq!(resolve("/api"), ("api:8080".to_string(), "/".to_string(), true));
+    }
+
+    #[test]
+    fn test_longest_prefix_wins() {
+        assert_eq!(resolve("/api/v2/users"), ("api-v2:8080".to_string(), "/next/users".to_string(), false));
+    }
+
+    #[test]
+    fn test_prefix_matches_whole_segments() {
+        assert_eq!(resolve("/apix"), ("web:80".to_string(), "/apix".to_string(), true));
+        assert_eq!(resolve("/static/app.js"), ("assets:9000".to_string(), "/static/app.js".to_string(), true));
+    }
+
+    #[test]
+    fn test_strip_and_rewrite_conflict() {
+        let config = Config::from_toml("[[vhosts]]\nhost = \"*\"\norigin = \"a:1\"\n[[vhosts.routes]]\nprefix = \"api\"\nstrip_prefix = true\nrewrite = \"/v1\"\n").unwrap();
+        let err = config.validate().unwrap_err();
+        assert!(err.contains("routes[0].prefix"));
+        assert!(err.contains("can not be combined"));
+    }
+}
@@ -0,0 +1,96 @@
+use axum::http::{uri::PathAndQuery, StatusCode};
+
+use crate::config::config::{Config, VhostConfig};
+
+use super::route::{RouteTable, RouteTarget};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HostPattern {
+    Exact(String),
+    /// `*.example.com`, holds `example.com` and matches any of its subdomains
+    Suffix(String),
+    Any,
+}
+
+#[derive(Debug, Clone)]
+pub struct VirtualHost {
+    pub pattern: HostPattern,
+    pub origin: String,
+    /// prefix for every cache key of this vhost so that two sites never share entries
+    pub namespace: String,
+    pub routes: RouteTable,
+}
+
+#[derive(Debug, Clone)]
+pub struct VirtualHosts {
+    hosts: Vec<VirtualHost>,
+    pub unknown_host_status: StatusCode,
+}
+
+impl HostPattern {
+    pub fn parse(pattern: &str) -> Self {
+        let pattern = pattern.trim().to_ascii_lowercase();
+        if pattern == "*" {
+            HostPattern::Any
+        } else if let Some(domain) = pattern.strip_prefix("*.") {
+            HostPattern::Suffix(domain.to_string())
+        } else {
+            HostPattern::Exact(pattern)
+        }
+    }
+
+    pub fn matches(&self, host: &str) -> bool {
+        match self {
+            HostPattern::Exact(name) => name == host,
+            HostPattern::Suffix(domain) => host.len() > domain.len() + 1 && host.ends_with(domain.as_str()) && host[..host.len() - domain.len()].ends_with('.'),
+            HostPattern::Any => true,
+        }
+    }
+
+    /// exact names win over wildcards and longer wildcard domains win over shorter ones
+    fn specificity(&self) -> usize {
+        match self {
+            HostPattern::Exact(_) => usize::MAX,
+            HostPattern::Suffix(domain) => domain.len() + 1,
+            HostPattern::Any => 0,
+        }
+    }
+}
+
+impl VirtualHost {
+    pub fn from_config(vhost: &VhostConfig) -> Self {
+        VirtualHost {
+            pattern: HostPattern::parse(&vhost.host),
+            origin: vhost.origin.clone(),
+            namespace: vhost.namespace(),
+            routes: RouteTable::from_config(&vhost.routes),
+        }
+    }
+
+    pub fn route(&self, p_and_q: &PathAndQuery) -> RouteTarget {
+        self.routes.resolve(&self.origin, p_and_q)
+    }
+}
+
+impl VirtualHosts {
+    pub fn new(mut hosts: Vec<VirtualHost>, unknown_host_status: StatusCode) -> Self {
+        hosts.sort_by_key(|host| std::cmp::Reverse(host.pattern.specificity()));
+        VirtualHosts { hosts, unknown_host_status }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        let hosts = config.effective_vhosts().iter().map(VirtualHost::from_config).collect();
+        let status = StatusCode::from_u16(config.proxy.unknown_host_status).unwrap_or(StatusCode::MISDIRECTED_REQUEST);
+        VirtualHosts::new(hosts, status)
+    }
+
+    /// finds the vhost for a `Host` header value, the port is ignored
+    pub fn resolve(&self, host: &str) -> Option<&VirtualHost> {
+        let host_name = match host.strip_prefix('[') {
+            Some(ipv6) => ipv6.split(']').next().unwrap_or(ipv6),
+            None => host.split(':').next().unwrap_or(host),
+        };
+        let host_name = host_name.trim_end_matches('.').to_ascii_lowercase();
+        self.hosts.iter().find(|vhost| vhost.pattern.matches(&host_name))
+    }
+}
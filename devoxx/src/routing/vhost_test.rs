@@ -0,0 +1,59 @@
+#[cfg(test)]
+mod vhost_test {
+    use axum::http::StatusCode;
+
+    use crate::{config::config::Config, routing::vhost::{HostPattern, VirtualHosts}};
+
+    fn vhosts() -> VirtualHosts {
+        let config = Config::from_toml(
+            r#"
+            [[vhosts]]
+            host = "*"
+            origin = "fallback:80"
+
+            [[vhosts]]
+            host = "*.example.com"
+            origin = "wild:80"
+
+            [[vhosts]]
+            host = "www.example.com"
+            origin = "www:80"
+            "#,
+        )
+        .unwrap();
+        VirtualHosts::from_config(&config)
+    }
+
+    #[test]
+    fn test_host_patterns() {
+        assert!(HostPattern::parse("*.example.com").matches("a.example.com"));
+        assert!(HostPattern::parse("*.example.com").matches("a.b.example.com"));
+        assert!(!HostPattern::parse("*.example.com").matches("example.com"));
+        assert!(!HostPattern::parse("*.example.com").matches("badexample.com"));
+        assert!(HostPattern::parse("Example.COM").matches("example.com"));
+    }
+
+    #[test]
+    fn test_most_specific_vhost_wins() {
+        let vhosts = vhosts();
+        assert_eq!(vhosts.resolve("www.example.com:3001").unwrap().origin, "www:80");
+        assert_eq!(vhosts.resolve("api.example.com").unwrap().origin, "wild:80");
+        assert_eq!(vhosts.resolve("other.org").unwrap().origin, "fallback:80");
+        assert_eq!(vhosts.resolve("api.example.com").unwrap().namespace, "*.example.com");
+    }
+
+    #[test]
+    fn test_unknown_host() {
+        let config = Config::from_toml("[proxy]\nunknown_host_status = 404\n[[vhosts]]\nhost = \"client.hello\"\norigin = \"localhost:3000\"\n").unwrap();
+        let vhosts = VirtualHosts::from_config(&config);
+        assert!(vhosts.resolve("client.hello").is_some());
+        assert!(vhosts.resolve("someone.else").is_none());
+        assert_eq!(vhosts.unknown_host_status, StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_without_vhosts_everything_goes_to_origin() {
+        let vhosts = VirtualHosts::from_config(&Config::default());
+        assert_eq!(vhosts.resolve("anything").unwrap().origin, "localhost:3000");
+    }
+}
@@ -18,7 +18,8 @@ pub trait Serializer<T> {
 pub fn cachekey_to_key(cacheKey : CacheKey) -> Key { 
     Key {
         method : cacheKey.0.to_string(),
-        url: cacheKey.1.to_string()
+        url: cacheKey.1.to_string(),
+        namespace: cacheKey.2
     }
 }
 
@@ -26,7 +27,7 @@ pub fn cachekey_to_key(cacheKey : CacheKey) -> Key {
 pub fn key_to_cachekey(key : Key) -> CacheKey { 
     let method : Method = key.method.parse().unwrap();
     let uri : Uri = key.url.parse().unwrap();
-    CacheKey(method, uri)
+    CacheKey(method, uri, key.namespace)
 }
 
 
@@ -43,13 +44,14 @@ pub fn cached_response_to_value(response : CachedResponse) -> Value {
             header_str.push('\n');
         }
     }
-    let plain_bytes: Vec<u8> = response.body.into_iter().collect();
-    let body = String::from_utf8(plain_bytes).unwrap();
     Value { 
         status: response.status.as_u16() as i32,
         headers : header_str, 
-        body: body, 
-        cached_at: response.cached_at.duration_since(UNIX_EPOCH).unwrap().as_secs().to_string()
+        body: response.body.to_vec(), 
+        cached_at: response.cached_at.duration_since(UNIX_EPOCH).unwrap().as_secs().to_string(),
+        variant: response.variant,
+        policy: response.policy,
+        ttl_override: response.ttl_override
     }
 }
 
@@ -57,13 +59,15 @@ pub fn cached_response_to_value(response : CachedResponse) -> Value {
 pub fn value_to_cache_response(value : Value) -> CachedResponse{
     let headers = parse_headers(value.headers);
     let status = StatusCode::from_u16(value.status as u16).unwrap();
-    let body_bytes  = value.body.as_bytes().to_vec();
-    let body = axum::body::Bytes::from(body_bytes);
+    let body = axum::body::Bytes::from(value.body);
     CachedResponse { 
         status, 
         headers, 
         body,
-        cached_at: SystemTime::UNIX_EPOCH + Duration::from_secs(value.cached_at.parse::<u64>().unwrap())
+        cached_at: SystemTime::UNIX_EPOCH + Duration::from_secs(value.cached_at.parse::<u64>().unwrap()),
+        variant: value.variant,
+        policy: value.policy,
+        ttl_override: value.ttl_override
     }
 
 }
\ No newline at end of file
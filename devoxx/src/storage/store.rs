@@ -16,7 +16,7 @@ use sqlx::{Row, Column};
 use hex::encode;
 use sqlx::types::Uuid as UUID;
 
-use crate::cache::cache_util::{CacheKey, CachedResponse, Cache};
+use crate::cache::{cache_util::{CacheKey, CachedResponse}, overrides::Override};
 
 use super::serializer::Serializer;
 
@@ -31,7 +31,9 @@ pub struct DbStore {
 pub struct Page { 
     pub id : Option<i32>,
     pub method: String,
-    pub url : String
+    pub url : String,
+    pub namespace : String,
+    pub variant : String
 }
 
 
@@ -42,13 +44,18 @@ pub struct Page_content {
     pub headers : String,
     pub body : Vec<u8>,
     pub cached_at : String,
-    pub page_key : Option<i32>
+    pub page_key : Option<i32>,
+    pub policy : Option<String>,
+    /// json of the `cache::overrides::Override` applied to the page
+    pub ttl_override : Option<String>
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Key { 
     pub method: String,
-    pub url : String
+    pub url : String,
+    #[serde(default)]
+    pub namespace : String
 }
 
 
@@ -76,8 +83,30 @@ impl ToRedisArgs for Key {
 pub struct Value { 
     pub status: i32,
     pub headers : String,
-    pub body : String,
+    #[serde(with = "base64_body")]
+    pub body : Vec<u8>,
     pub cached_at : String,
+    #[serde(default)]
+    pub variant : String,
+    #[serde(default)]
+    pub policy : Option<String>,
+    #[serde(default)]
+    pub ttl_override : Option<Override>,
+}
+
+/// redis entries are json, the body goes in as base64 so binary ones (images, gzip) survive
+mod base64_body { 
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(body : &[u8], serializer : S) -> Result<S::Ok, S::Error> { 
+        serializer.serialize_str(&STANDARD.encode(body))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer : D) -> Result<Vec<u8>, D::Error> { 
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD.decode(encoded).map_err(D::Error::custom)
+    }
 }
 
 impl Serializer<CacheKey> for Page {
@@ -85,14 +114,16 @@ impl Serializer<CacheKey> for Page {
         Page { 
             id: None,
             method: t.0.to_string(),
-            url : t.1.to_string()
+            url : t.1.to_string(),
+            namespace : t.2,
+            variant : String::new()
         }
     }
 
     fn deserialize(&self) -> CacheKey {
         let method: Method = self.method.parse().unwrap(); // Assuming self.method is a valid HTTP method string
         let uri: Uri = self.url.parse().unwrap(); // Assuming self.url is a valid URI string
-        CacheKey::new(method, uri)
+        CacheKey::namespaced(&self.namespace, method, uri)
     }
 }
 
@@ -118,7 +149,9 @@ impl Serializer<CachedResponse> for Page_content {
             headers: header_str,
             body: plain_bytes,
             cached_at: t.cached_at.duration_since(UNIX_EPOCH).unwrap().as_secs().to_string(),
-            page_key: None
+            page_key: None,
+            policy: t.policy,
+            ttl_override: t.ttl_override.and_then(|ttl_override| serde_json::to_string(&ttl_override).ok())
         }
     }
 
@@ -128,10 +161,12 @@ impl Serializer<CachedResponse> for Page_content {
        let cached_at = SystemTime::UNIX_EPOCH + Duration::from_secs(self.cached_at.parse::<u64>().unwrap());
        println!("actual content-length : {}", self.body.clone().len());
        let body = axum::body::Bytes::from_iter(self.body.clone());
-       CachedResponse::new(status, headers, body, cached_at)
+       let ttl_override = self.ttl_override.as_deref().and_then(|ttl_override| serde_json::from_str(ttl_override).ok());
+       CachedResponse::new(status, headers, body, cached_at).with_policy(self.policy.clone()).with_override(ttl_override)
     }
 }
 
+/// one header per line, repeated lines such as two `Vary` headers are all kept
 pub fn parse_headers(headers_str: String) -> HeaderMap { 
     let mut header_map = HeaderMap::new();
     for line in headers_str.lines() {
@@ -142,7 +177,7 @@ pub fn parse_headers(headers_str: String) -> HeaderMap {
             if !name.is_empty() && !value.is_empty() {
                 if let Ok(name) = HeaderName::from_bytes(name.as_bytes()) {
                     if let Ok(value) = HeaderValue::from_str(value) {
-                        header_map.insert(name, value);
+                        header_map.append(name, value);
                     }
                 }
             }
@@ -184,24 +219,28 @@ impl DbStore {
 
 
     pub async  fn add(&mut self, key: CacheKey, content : CachedResponse) -> Result<(), String>{ 
-        let page = Page::serialize(key);
+        // a key holds one page per variant, storing it again replaces the old one
+        self.remove_variant(key.clone(), &content.variant).await?;
+        let mut page = Page::serialize(key);
+        page.variant = content.variant.clone();
         let mut page_content = Page_content::serialize(content);
         //page_content.page_key = Some(page.id);
-        let stmt = format!("INSERT INTO Page(method, uri) VALUES('{}', '{}');", page.method, page.url);
-        let result = self.pool.execute(stmt.as_str()).await.map_err(|err| err.to_string());
+        let result = query("INSERT INTO Page(method, uri, namespace, variant) VALUES(?, ?, ?, ?);")
+            .bind(&page.method).bind(&page.url).bind(&page.namespace).bind(&page.variant)
+            .execute(&self.pool).await.map_err(|err| err.to_string());
         match result {
             Ok(qResult ) => {
                 println!("{:#?}", qResult);
-                let id : i32 = query("SELECT * FROM Page where method = ? AND uri = ?").bind(page.method).bind(page.url).fetch_one(&self.pool).await.unwrap().get(0);
+                let id : i32 = qResult.last_insert_rowid() as i32;
                 page_content.page_key = Some(id);
                 let body_hex = encode(&page_content.body);
                 let decoded = hex::decode(body_hex.clone()).unwrap();
                 println!("decoded length from body hex is {}", decoded.len());
                 let page_id = page_content.page_key.unwrap();
                 println!("page id : {}", page_id);
-                let stmt = format!("INSERT INTO Page_content( response_status, headers, body, cached_at, page_id) VALUES({}, '{}', ?, '{}', {});", 
-                   page_content.status, page_content.headers, page_content.cached_at, page_content.page_key.unwrap());
-                let result = sqlx::query(&stmt).bind(page_content.body).execute(&self.pool).await.map_err(|err| err.to_string());
+                let result = sqlx::query("INSERT INTO Page_content( response_status, headers, body, cached_at, page_id, policy, ttl_override) VALUES(?, ?, ?, ?, ?, ?, ?);")
+                    .bind(page_content.status).bind(page_content.headers).bind(page_content.body).bind(page_content.cached_at).bind(page_id).bind(page_content.policy).bind(page_content.ttl_override)
+                    .execute(&self.pool).await.map_err(|err| err.to_string());
                 match result { 
                     Ok(qResult) =>  {
                         println!("{:#?}", qResult);
@@ -221,8 +260,9 @@ impl DbStore {
     }
     pub async fn find_page_and_content(&self , key: CacheKey) -> CachedResponse{ 
         let page = Page::serialize(key);
-        let query_str = format!("SELECT * FROM Page WHERE method = '{}' AND uri = '{}';", page.method, page.url);
-        let result = query(query_str.as_str()).fetch_one(&self.pool).await.map_err(|err| err.to_string()).unwrap();
+        let result = query("SELECT * FROM Page WHERE method = ? AND uri = ? AND namespace = ?;")
+            .bind(page.method).bind(page.url).bind(page.namespace)
+            .fetch_one(&self.pool).await.map_err(|err| err.to_string()).unwrap();
         //println!("result :{:?}", result);
         let id: i32= result.get_unchecked(0);
         println!("uuid : {}", id);
@@ -235,9 +275,93 @@ impl DbStore {
         println!("retrived content-length : {}", body.clone().len());
         let cached_at: String = content_row.get(4);
         let page_key: i32 = content_row.get(5);
-        let content = Page_content { id: Some(content_id), status: status, headers: headers, body: body, cached_at: cached_at, page_key: Some(page_key)};
+        let policy: Option<String> = content_row.get(6);
+        let ttl_override: Option<String> = content_row.get(7);
+        let content = Page_content { id: Some(content_id), status: status, headers: headers, body: body, cached_at: cached_at, page_key: Some(page_key), policy, ttl_override};
         //println!("content - {:#?}", content);
         let cached_content = content.deserialize();
         cached_content
     }
-}
\ No newline at end of file
+
+    /// every stored variant of the key, `cache::vary::select` picks the one for a request
+    pub async fn find_variants(&self, key: CacheKey) -> Result<Vec<CachedResponse>, String> { 
+        let page = Page::serialize(key);
+        let rows = query("SELECT Page.variant, Page_content.response_status, Page_content.headers, Page_content.body, Page_content.cached_at, Page_content.policy, Page_content.ttl_override FROM Page JOIN Page_content ON Page_content.page_id = Page.id WHERE Page.method = ? AND Page.uri = ? AND Page.namespace = ?;")
+            .bind(page.method).bind(page.url).bind(page.namespace)
+            .fetch_all(&self.pool).await.map_err(|err| err.to_string())?;
+        let variants = rows.iter().map(|row| { 
+            let variant: String = row.get(0);
+            let content = Page_content { id: None, status: row.get(1), headers: row.get(2), body: row.get(3), cached_at: row.get(4), page_key: None, policy: row.get(5), ttl_override: row.get(6) };
+            content.deserialize().with_variant(variant)
+        }).collect();
+        Ok(variants)
+    }
+
+    /// rewrites headers, cached_at, policy state and override of a stored page after a 304, the body is left untouched
+    pub async fn refresh(&mut self, key: CacheKey, content : CachedResponse) -> Result<u64, String> { 
+        let page = Page::serialize(key);
+        let variant = content.variant.clone();
+        let page_content = Page_content::serialize(content);
+        let result = query("UPDATE Page_content SET headers = ?, cached_at = ?, policy = ?, ttl_override = ? WHERE page_id IN (SELECT id FROM Page WHERE method = ? AND uri = ? AND namespace = ? AND variant = ?);")
+            .bind(page_content.headers).bind(page_content.cached_at).bind(page_content.policy).bind(page_content.ttl_override)
+            .bind(page.method).bind(page.url).bind(page.namespace).bind(variant)
+            .execute(&self.pool).await.map_err(|err| err.to_string())?;
+        Ok(result.rows_affected())
+    }
+
+    /// deletes one variant of the key together with its content
+    pub async fn remove_variant(&mut self, key: CacheKey, variant : &str) -> Result<u64, String> { 
+        let page = Page::serialize(key);
+        query("DELETE FROM Page_content WHERE page_id IN (SELECT id FROM Page WHERE method = ? AND uri = ? AND namespace = ? AND variant = ?);")
+            .bind(&page.method).bind(&page.url).bind(&page.namespace).bind(variant)
+            .execute(&self.pool).await.map_err(|err| err.to_string())?;
+        let result = query("DELETE FROM Page WHERE method = ? AND uri = ? AND namespace = ? AND variant = ?;")
+            .bind(page.method).bind(page.url).bind(page.namespace).bind(variant)
+            .execute(&self.pool).await.map_err(|err| err.to_string())?;
+        Ok(result.rows_affected())
+    }
+
+    /// deletes every variant stored for the key together with its content
+    pub async fn remove(&mut self, key: CacheKey) -> Result<u64, String> { 
+        let page = Page::serialize(key);
+        query("DELETE FROM Page_content WHERE page_id IN (SELECT id FROM Page WHERE method = ? AND uri = ? AND namespace = ?);")
+            .bind(&page.method).bind(&page.url).bind(&page.namespace)
+            .execute(&self.pool).await.map_err(|err| err.to_string())?;
+        let result = query("DELETE FROM Page WHERE method = ? AND uri = ? AND namespace = ?;")
+            .bind(page.method).bind(page.url).bind(page.namespace)
+            .execute(&self.pool).await.map_err(|err| err.to_string())?;
+        Ok(result.rows_affected())
+    }
+
+    /// up to `limit` stored pages with an id above `after`, in id order and without their bodies
+    pub async fn page_batch(&self, after: i64, limit: usize) -> Result<Vec<(i64, CachedResponse)>, String> { 
+        let rows = query("SELECT Page.id, Page.variant, Page_content.response_status, Page_content.headers, Page_content.cached_at, Page_content.policy, Page_content.ttl_override FROM Page JOIN Page_content ON Page_content.page_id = Page.id WHERE Page.id > ? ORDER BY Page.id LIMIT ?;")
+            .bind(after).bind(limit as i64)
+            .fetch_all(&self.pool).await.map_err(|err| err.to_string())?;
+        let pages = rows.iter().map(|row| { 
+            let variant: String = row.get(1);
+            let content = Page_content { id: None, status: row.get(2), headers: row.get(3), body: Vec::new(), cached_at: row.get(4), page_key: None, policy: row.get(5), ttl_override: row.get(6) };
+            (row.get::<i64, _>(0), content.deserialize().with_variant(variant))
+        }).collect();
+        Ok(pages)
+    }
+
+    /// deletes the pages with these ids together with their content
+    pub async fn remove_pages(&mut self, ids: &[i64]) -> Result<u64, String> { 
+        if ids.is_empty() { 
+            return Ok(0);
+        }
+        let placeholders = vec!["?"; ids.len()].join(", ");
+        let content = format!("DELETE FROM Page_content WHERE page_id IN ({});", placeholders);
+        let pages = format!("DELETE FROM Page WHERE id IN ({});", placeholders);
+        let mut content_query = query(&content);
+        let mut pages_query = query(&pages);
+        for id in ids { 
+            content_query = content_query.bind(*id);
+            pages_query = pages_query.bind(*id);
+        }
+        content_query.execute(&self.pool).await.map_err(|err| err.to_string())?;
+        let result = pages_query.execute(&self.pool).await.map_err(|err| err.to_string())?;
+        Ok(result.rows_affected())
+    }
+}
@@ -9,7 +9,7 @@ mod storeTests {
 
     
     
-    use crate::{storage::{serializer::{cached_response_to_value, cachekey_to_key, key_to_cachekey, value_to_cache_response, Serializer}, store::*}, CacheKey, CachedResponse};
+    use crate::{cache::vary, storage::{serializer::{cached_response_to_value, cachekey_to_key, key_to_cachekey, value_to_cache_response, Serializer}, store::*}, CacheKey, CachedResponse};
     use std::{fs::{read, OpenOptions}, io::Read, str::FromStr, time::SystemTime};
     use axum::{body::{self, Body, Bytes, HttpBody}, extract::Host, http::{method, uri::{self, PathAndQuery}, HeaderMap, HeaderName, HeaderValue, Method, Request, Response, StatusCode, Uri}, response::IntoResponse, routing::*, RequestExt, Router};
     use reqwest::Url;
@@ -71,12 +71,16 @@ mod storeTests {
             key: Key {
                 method: "GET".to_string(),
                 url: "http://localhost:3000/fast".to_string(),
+                namespace: String::new(),
             },
             value: Value {
                 status: 200,
                 headers: "content-type:text/html; charset=utf-8\ncache-control:max-age=3700\ncontent-length:274\ndate:Mon, 08 Apr 2024 12:43:21 GMT".to_string(),
-                body: "<script src=\"https://cdn.tailwindcss.com\"></script><body class=\"flex flex-col items-center justify-center h-screen\"><h1 class=\"text-6xl\">Fast</h1><p class=\"text-4xl\">2024-04-08 12:43:21.034713500 UTC</p><a class=\"text-blue-400 pt-16 text-xl\" href=\"/\">Go back home</a></body>".to_string(),
+                body: "<script src=\"https://cdn.tailwindcss.com\"></script><body class=\"flex flex-col items-center justify-center h-screen\"><h1 class=\"text-6xl\">Fast</h1><p class=\"text-4xl\">2024-04-08 12:43:21.034713500 UTC</p><a class=\"text-blue-400 pt-16 text-xl\" href=\"/\">Go back home</a></body>".as_bytes().to_vec(),
                 cached_at: "1712580201".to_string(),
+                variant: String::new(),
+                policy: None,
+                ttl_override: None,
             },
         };
         let cache_key = key_to_cachekey(cached.key);
@@ -156,9 +160,9 @@ mod storeTests {
         let content = Page_content::serialize(cached_response);
         let client = reqwest::Client::new();
         let url = Url::from_str("http://localhost:6000/api/set").unwrap();
-        let key = Key { method: page.method, url: page.url};
+        let key = Key { method: page.method, url: page.url, namespace: page.namespace};
         //let content_body_str = serde_json::from_slice(&content.body);
-        let value = Value {status: content.status, headers : content.headers, body: String::from_utf8(content.body).unwrap() , cached_at: content.cached_at};
+        let value = Value {status: content.status, headers : content.headers, body: content.body, cached_at: content.cached_at, variant: String::new(), policy: None, ttl_override: None};
         let cacheable = cacheableBody{ key : key.clone(), value : value};
         let b = reqwest::Body::from(cacheable);
         let (status, headerMap, body) = client.request(Method::from_str("POST").unwrap(), url).body(b).send().await
@@ -225,4 +229,85 @@ mod storeTests {
         println!("Retrieved : {:?}", headers);
     }
 
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_refresh_and_replace() { 
+        let mut store = DbStore::new("sqlite::memory:".to_string()).await.unwrap();
+        let cache_key = CacheKey::namespaced("site-a", Method::GET, Uri::from_str("http://localhost:3000/page").unwrap());
+        let mut headers = HeaderMap::new();
+        headers.insert(HeaderName::from_str("etag").unwrap(), HeaderValue::from_str("\"v1\"").unwrap());
+        let first = CachedResponse::new(StatusCode::OK, headers.clone(), Bytes::from_static(b"body"), SystemTime::UNIX_EPOCH);
+        store.add(cache_key.clone(), first).await.unwrap();
+
+        headers.insert(HeaderName::from_str("cache-control").unwrap(), HeaderValue::from_str("max-age=60").unwrap());
+        let refreshed = CachedResponse::new(StatusCode::OK, headers.clone(), Bytes::new(), SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1000));
+        assert_eq!(store.refresh(cache_key.clone(), refreshed).await.unwrap(), 1);
+        let found = store.find_page_and_content(cache_key.clone()).await;
+        assert_eq!(found.body, Bytes::from_static(b"body"));
+        assert_eq!(found.headers.get("cache-control").unwrap(), "max-age=60");
+        assert_eq!(found.cached_at, SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1000));
+
+        let replaced = CachedResponse::new(StatusCode::OK, headers, Bytes::from_static(b"new body"), SystemTime::UNIX_EPOCH);
+        store.add(cache_key.clone(), replaced).await.unwrap();
+        assert_eq!(store.find_page_and_content(cache_key.clone()).await.body, Bytes::from_static(b"new body"));
+        assert_eq!(store.remove(cache_key).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_variants_are_stored_side_by_side() { 
+        let mut store = DbStore::new("sqlite::memory:".to_string()).await.unwrap();
+        let cache_key = CacheKey::new(Method::GET, Uri::from_str("http://localhost:3000/lang").unwrap());
+        let mut headers = HeaderMap::new();
+        headers.insert(HeaderName::from_str("vary").unwrap(), HeaderValue::from_str("accept-language").unwrap());
+        let en = CachedResponse::new(StatusCode::OK, headers.clone(), Bytes::from_static(b"hello"), SystemTime::now()).with_variant("accept-language=en".to_string());
+        let fr = CachedResponse::new(StatusCode::OK, headers, Bytes::from_static(b"bonjour"), SystemTime::now()).with_variant("accept-language=fr".to_string()).with_policy(Some("{\"state\":1}".to_string()));
+        store.add(cache_key.clone(), en).await.unwrap();
+        store.add(cache_key.clone(), fr).await.unwrap();
+        let mut variants = store.find_variants(cache_key.clone()).await.unwrap();
+        variants.sort_by(|a, b| a.variant.cmp(&b.variant));
+        assert_eq!(variants.len(), 2);
+        assert_eq!(variants[0].body, Bytes::from_static(b"hello"));
+        assert_eq!(variants[1].variant, "accept-language=fr");
+        assert_eq!(variants[1].policy.as_deref(), Some("{\"state\":1}"));
+        assert_eq!(store.remove_variant(cache_key.clone(), "accept-language=en").await.unwrap(), 1);
+        assert_eq!(store.find_variants(cache_key).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_repeated_headers_survive_both_stores() { 
+        let mut headers = HeaderMap::new();
+        headers.append("vary", HeaderValue::from_static("accept-encoding"));
+        headers.append("vary", HeaderValue::from_static("accept-language"));
+        let mut request = HeaderMap::new();
+        request.insert("accept-encoding", HeaderValue::from_static("gzip"));
+        request.insert("accept-language", HeaderValue::from_static("fr"));
+        let variant = vary::variant_key(&headers, &request).unwrap();
+        let cached = CachedResponse::new(StatusCode::OK, headers, Bytes::from_static(b"bonjour"), SystemTime::now()).with_variant(variant);
+
+        let from_redis = value_to_cache_response(cached_response_to_value(cached.clone()));
+        assert_eq!(from_redis.headers.get_all("vary").iter().count(), 2);
+        assert!(vary::matches(&from_redis, &request, ""));
+
+        let mut store = DbStore::new("sqlite::memory:".to_string()).await.unwrap();
+        let cache_key = CacheKey::new(Method::GET, Uri::from_str("http://localhost:3000/negotiated").unwrap());
+        store.add(cache_key.clone(), cached).await.unwrap();
+        let from_sqlite = store.find_variants(cache_key).await.unwrap().remove(0);
+        assert!(vary::matches(&from_sqlite, &request, ""));
+    }
+
+    #[tokio::test]
+    async fn test_binary_body_survives_both_stores() { 
+        let body = Bytes::from_static(&[0x89, b'P', b'N', b'G', 0x00, 0xff, 0xfe, 0x80]);
+        let cached = CachedResponse::new(StatusCode::OK, HeaderMap::new(), body.clone(), SystemTime::now());
+        let key = Key { method: "GET".to_string(), url: "http://localhost:3000/logo.png".to_string(), namespace: String::new() };
+
+        let stored = serde_json::to_vec(&cacheableBody { key, value: cached_response_to_value(cached.clone()) }).unwrap();
+        let read_back : cacheableBody = serde_json::from_slice(&stored).unwrap();
+        assert_eq!(value_to_cache_response(read_back.value).body, body);
+
+        let mut store = DbStore::new("sqlite::memory:".to_string()).await.unwrap();
+        let cache_key = CacheKey::new(Method::GET, Uri::from_str("http://localhost:3000/logo.png").unwrap());
+        store.add(cache_key.clone(), cached).await.unwrap();
+        assert_eq!(store.find_variants(cache_key).await.unwrap().remove(0).body, body);
+    }
+}